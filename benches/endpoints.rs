@@ -0,0 +1,226 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Times the index, JSON and ICS endpoints over synthetic events, comparing the handlers as they are,
+// borrowing events from the shared store, with the way they used to work, when each request got
+// its own clone of the whole dataset and cloned the matching events again. Run with
+// `cargo bench -- [event count]`.
+
+use axum::{
+    extract::{Extension, Query, TypedHeader},
+    headers::Host,
+    http::uri::Authority,
+};
+use chrono::{Duration, NaiveDate, Utc};
+use dancelist::{
+    controllers::index,
+    errors::InternalError,
+    icalendar::events_to_calendar,
+    model::{
+        dancestyle::DanceStyle,
+        event::{Event, EventTime},
+        events::Events,
+        filters::Filters,
+        price::Price,
+    },
+    store::EventStore,
+};
+use eyre::{eyre, Report};
+use std::{
+    env,
+    fs::{create_dir_all, remove_dir_all, write},
+    future::Future,
+    process,
+    time::Instant,
+};
+
+/// The number of events to use if no count is given on the command line.
+const DEFAULT_EVENT_COUNT: usize = 5000;
+
+/// The number of times to call each endpoint.
+const ITERATIONS: u32 = 20;
+
+#[tokio::main]
+async fn main() -> Result<(), Report> {
+    // `cargo bench` passes `--bench`, so take the first argument which isn't a flag.
+    let event_count = env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-'))
+        .map_or(Ok(DEFAULT_EVENT_COUNT), |count| count.parse())?;
+    let store = synthetic_store(event_count)?;
+    println!(
+        "{} events, mean of {} requests:",
+        store.events().events.len(),
+        ITERATIONS
+    );
+    println!("{:<12} {:>12} {:>12}", "endpoint", "cloned", "shared");
+
+    time_endpoint(
+        "index",
+        &store,
+        |store| async move {
+            // The old handler rendered the same template, but from its own copy of the dataset and
+            // with a clone of each matching event.
+            let events = Events::clone(&store.events());
+            let matching: Vec<Event> = events
+                .matching(&Filters::default())
+                .into_iter()
+                .cloned()
+                .collect();
+            let html = index_html(store).await;
+            drop((events, matching));
+            html
+        },
+        index_html,
+    )
+    .await?;
+    time_endpoint(
+        "index.json",
+        &store,
+        |store| async move {
+            let events = Events::clone(&store.events());
+            let mut events = events.matching(&Filters::default());
+            events.sort_by_key(|event| event.start_time_sort_key());
+            let events = Events {
+                events: events.into_iter().cloned().collect(),
+            };
+            Ok(serde_json::to_string(&events)?)
+        },
+        |store| async move { index::index_json(Extension(store), Query(Filters::default())).await },
+    )
+    .await?;
+    time_endpoint(
+        "index.ics",
+        &store,
+        |store| async move {
+            let events = Events::clone(&store.events());
+            let mut events = events.matching(&Filters::default());
+            events.sort_by_key(|event| event.start_time_sort_key());
+            Ok(events_to_calendar(&events))
+        },
+        |store| async move {
+            let ics = index::index_ics(Extension(store), Query(Filters::default())).await?;
+            Ok(ics.0)
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Renders the index page with the current handler.
+async fn index_html(store: EventStore) -> Result<String, InternalError> {
+    let host = TypedHeader(Host::from(Authority::from_static("folkdance.page")));
+    let html = index::index(Extension(store), Query(Filters::default()), host).await?;
+    Ok(html.0)
+}
+
+/// Calls the old and current versions of an endpoint repeatedly, checks that they give the same
+/// response, and prints the mean time taken by each.
+async fn time_endpoint<Cloned, ClonedFut, Shared, SharedFut>(
+    name: &str,
+    store: &EventStore,
+    cloned: Cloned,
+    shared: Shared,
+) -> Result<(), Report>
+where
+    Cloned: Fn(EventStore) -> ClonedFut,
+    ClonedFut: Future<Output = Result<String, InternalError>>,
+    Shared: Fn(EventStore) -> SharedFut,
+    SharedFut: Future<Output = Result<String, InternalError>>,
+{
+    if cloned(store.clone()).await.map_err(report)?
+        != shared(store.clone()).await.map_err(report)?
+    {
+        return Err(eyre!("Responses for {} differ", name));
+    }
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        cloned(store.clone()).await.map_err(report)?;
+    }
+    let cloned_time = start.elapsed() / ITERATIONS;
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        shared(store.clone()).await.map_err(report)?;
+    }
+    let shared_time = start.elapsed() / ITERATIONS;
+
+    println!("{:<12} {:>12?} {:>12?}", name, cloned_time, shared_time);
+    Ok(())
+}
+
+/// Writes the given number of events spread over the next two years to a temporary directory, and
+/// loads them into a new store.
+fn synthetic_store(event_count: usize) -> Result<EventStore, Report> {
+    let cities = ["London", "Bristol", "Brussels", "Gent", "Paris", "Berlin"];
+    let styles = [
+        DanceStyle::Balfolk,
+        DanceStyle::Contra,
+        DanceStyle::Playford,
+    ];
+    let today = Utc::today().naive_utc();
+    let events = (0..event_count)
+        .map(|i| {
+            let start_date: NaiveDate = today + Duration::days((i % 730) as i64);
+            Event {
+                id: None,
+                name: format!("Dance {}", i),
+                details: Some("Workshop followed by a dance.".to_string()),
+                links: vec![format!("https://example.com/dance/{}", i)],
+                time: EventTime::DateOnly {
+                    start_date,
+                    end_date: start_date + Duration::days((i % 3) as i64),
+                },
+                timezone: None,
+                country: "Somewhere".to_string(),
+                city: cities[i % cities.len()].to_string(),
+                region: None,
+                styles: vec![styles[i % styles.len()]],
+                workshop: i % 2 == 0,
+                social: true,
+                bands: vec![format!("Band {}", i % 50)],
+                callers: vec![],
+                teachers: vec![],
+                price: Some(Price::parse("€8-€12")),
+                organisation: Some(format!("Organisation {}", i % 20)),
+                cancelled: false,
+                tentative: false,
+                venue: None,
+                schedule: vec![],
+                band_details: vec![],
+                recurrence: None,
+                source: None,
+            }
+        })
+        .collect();
+
+    let directory = env::temp_dir().join(format!("dancelist-bench-{}", process::id()));
+    create_dir_all(&directory)?;
+    write(
+        directory.join("events.yaml"),
+        serde_yaml::to_string(&Events { events })?,
+    )?;
+    let store = EventStore::load(&directory);
+    remove_dir_all(&directory)?;
+    store
+}
+
+fn report(error: InternalError) -> Report {
+    match error {
+        InternalError::Internal(report) => report,
+        InternalError::NotFound => eyre!("Not found"),
+    }
+}
@@ -12,9 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::{importers::sync::MissingPolicy, model::dancestyle::DanceStyle};
+use chrono_tz::Tz;
 use eyre::{bail, Report, WrapErr};
 use serde::Deserialize;
 use std::{
+    collections::HashMap,
     fs::read_to_string,
     net::SocketAddr,
     path::{Path, PathBuf},
@@ -33,6 +36,120 @@ pub struct Config {
     pub events_dir: PathBuf,
     #[serde(default = "default_bind_address")]
     pub bind_address: SocketAddr,
+    /// How often to check the events directory for changes, in seconds.
+    #[serde(default = "default_reload_interval_secs")]
+    pub reload_interval_secs: u64,
+    /// Whether to serve the reload status page at `/admin/status`. It isn't authenticated and shows
+    /// the full error from the last failed reload, including file paths, so it is off by default.
+    #[serde(default)]
+    pub admin_status: bool,
+    /// Settings for the `lint` command.
+    #[serde(default)]
+    pub lint: LintConfig,
+    /// Settings for the `import` command.
+    #[serde(default)]
+    pub importers: ImportersConfig,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LintConfig {
+    /// Lint rules to enable or disable, by name. Rules which aren't listed are enabled.
+    #[serde(default)]
+    pub rules: HashMap<String, bool>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ImportersConfig {
+    /// What to do with previously imported events which are no longer upstream, when importing
+    /// into an existing file.
+    #[serde(default)]
+    pub missing: MissingPolicy,
+    /// Settings for each import source, by name.
+    #[serde(flatten)]
+    pub sources: HashMap<String, SourceConfig>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SourceConfig {
+    /// The URL from which to fetch events, instead of the source's usual URL.
+    pub url: Option<String>,
+    /// How many days before today to import events from, or `None` for no limit.
+    pub days_before: Option<i64>,
+    /// How many days after today to import events until, or `None` for no limit.
+    pub days_after: Option<i64>,
+    /// The types of events to import, for sources which distinguish them, or `None` for the
+    /// source's default types.
+    pub types: Option<Vec<String>>,
+    /// The kind of source, for sources which aren't built in.
+    pub kind: Option<SourceKind>,
+    /// Values for fields of imported events which the source doesn't provide.
+    #[serde(default)]
+    pub defaults: EventDefaults,
+    /// Credit to show for imported events, instead of the source's usual attribution.
+    pub attribution: Option<String>,
+    /// The licence under which the source's data may be used, if not known by the importer.
+    pub licence: Option<String>,
+    /// A file of rules for dropping or rewriting events, instead of the importer's built-in rules.
+    pub rules: Option<PathBuf>,
+    /// Dance styles for the source's own style names, in addition to or overriding those the
+    /// importer knows about. Names are matched ignoring case.
+    #[serde(default)]
+    pub style_mapping: HashMap<String, Vec<DanceStyle>>,
+}
+
+/// A kind of source which can be configured with any name.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    /// An iCalendar feed.
+    Ics,
+    /// Web pages with schema.org events embedded as JSON-LD.
+    JsonLd,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct EventDefaults {
+    /// The dance styles of all events.
+    #[serde(default)]
+    pub styles: Vec<DanceStyle>,
+    /// The organisation of all events.
+    pub organisation: Option<String>,
+    /// The country of events whose location doesn't include one.
+    pub country: Option<String>,
+    /// The city of events whose location doesn't include one.
+    pub city: Option<String>,
+    /// The timezone of events, used for times which are given without one.
+    pub timezone: Option<Tz>,
+    /// Whether events include a workshop.
+    #[serde(default)]
+    pub workshop: bool,
+    /// Whether events include a social dance.
+    #[serde(default = "default_social")]
+    pub social: bool,
+}
+
+impl Default for EventDefaults {
+    fn default() -> Self {
+        Self {
+            styles: vec![],
+            organisation: None,
+            country: None,
+            city: None,
+            timezone: None,
+            workshop: false,
+            social: default_social(),
+        }
+    }
+}
+
+impl ImportersConfig {
+    /// Gets the settings for the import source with the given name.
+    pub fn source(&self, name: &str) -> SourceConfig {
+        self.sources.get(name).cloned().unwrap_or_default()
+    }
 }
 
 impl Config {
@@ -48,6 +165,16 @@ impl Config {
         );
     }
 
+    /// Reads the config file if there is one, or otherwise returns the default config.
+    pub fn from_file_or_default() -> Result<Config, Report> {
+        for filename in &CONFIG_FILENAMES {
+            if Path::new(filename).is_file() {
+                return Config::read(filename);
+            }
+        }
+        Ok(toml::from_str("")?)
+    }
+
     fn read(filename: &str) -> Result<Config, Report> {
         let config_file =
             read_to_string(filename).wrap_err_with(|| format!("Reading {}", filename))?;
@@ -67,6 +194,14 @@ fn default_bind_address() -> SocketAddr {
     "0.0.0.0:3002".parse().unwrap()
 }
 
+fn default_reload_interval_secs() -> u64 {
+    60
+}
+
+fn default_social() -> bool {
+    true
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
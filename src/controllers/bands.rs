@@ -12,11 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{errors::InternalError, model::events::Events};
+use crate::{errors::InternalError, store::EventStore};
 use askama::Template;
 use axum::{extract::Extension, response::Html};
 
-pub async fn bands(Extension(events): Extension<Events>) -> Result<Html<String>, InternalError> {
+pub async fn bands(Extension(store): Extension<EventStore>) -> Result<Html<String>, InternalError> {
+    let events = store.events();
     let bands = events.bands();
     let template = BandsTemplate { bands };
     Ok(Html(template.render()?))
@@ -14,15 +14,16 @@
 
 use crate::{
     errors::InternalError,
-    model::{
-        events::{Country, Events},
-        filters::Filters,
-    },
+    model::{events::Country, filters::Filters},
+    store::EventStore,
 };
 use askama::Template;
 use axum::{extract::Extension, response::Html};
 
-pub async fn cities(Extension(events): Extension<Events>) -> Result<Html<String>, InternalError> {
+pub async fn cities(
+    Extension(store): Extension<EventStore>,
+) -> Result<Html<String>, InternalError> {
+    let events = store.events();
     let countries = events.countries(&Filters::all());
     let template = CitiesTemplate { countries };
     Ok(Html(template.render()?))
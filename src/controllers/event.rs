@@ -0,0 +1,50 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{
+    errors::InternalError,
+    icalendar::{events_to_calendar, Ics},
+    model::event::Event,
+    store::EventStore,
+};
+use askama::Template;
+use axum::{
+    extract::{Extension, Path},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+
+/// Shows a single event, as HTML or as an `.ics` or `.json` file depending on the extension.
+pub async fn event(
+    Extension(store): Extension<EventStore>,
+    Path(id): Path<String>,
+) -> Result<Response, InternalError> {
+    if let Some(id) = id.strip_suffix(".ics") {
+        let event = store.find(id).ok_or(InternalError::NotFound)?;
+        Ok(Ics(events_to_calendar(&[&event])).into_response())
+    } else if let Some(id) = id.strip_suffix(".json") {
+        let event = store.find(id).ok_or(InternalError::NotFound)?;
+        Ok(Json(event).into_response())
+    } else {
+        let event = store.find(&id).ok_or(InternalError::NotFound)?;
+        let template = EventTemplate { event: &event };
+        Ok(Html(template.render()?).into_response())
+    }
+}
+
+#[derive(Template)]
+#[template(path = "event.html")]
+struct EventTemplate<'a> {
+    event: &'a Event,
+}
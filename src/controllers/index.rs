@@ -13,14 +13,16 @@
 // limitations under the License.
 
 use crate::{
+    csv::{events_to_csv, Csv},
     errors::InternalError,
     icalendar::{events_to_calendar, Ics},
     model::{
         dancestyle::DanceStyle,
         event::Event,
-        events::{Country, Events},
+        events::{Country, EventsRef},
         filters::Filters,
     },
+    store::EventStore,
 };
 use askama::Template;
 use axum::{
@@ -31,10 +33,11 @@ use axum::{
 use chrono::{naive, Datelike, NaiveDate};
 
 pub async fn index(
-    Extension(events): Extension<Events>,
+    Extension(store): Extension<EventStore>,
     Query(mut filters): Query<Filters>,
     TypedHeader(host): TypedHeader<Host>,
 ) -> Result<Html<String>, InternalError> {
+    let events = store.events();
     let has_filters = filters.has_some();
 
     if host.hostname().contains("balfolk.org") && filters.style.is_none() {
@@ -49,8 +52,7 @@ pub async fn index(
     } else {
         vec![]
     };
-    let events = events.matching(&filters);
-    let months = sort_and_group_by_month(events);
+    let months = sort_and_group_by_month(events.matching(&filters));
     let template = IndexTemplate {
         filters,
         months,
@@ -63,39 +65,55 @@ pub async fn index(
 }
 
 pub async fn index_json(
-    Extension(events): Extension<Events>,
+    Extension(store): Extension<EventStore>,
     Query(filters): Query<Filters>,
 ) -> Result<String, InternalError> {
+    let events = store.events();
     let mut events = events.matching(&filters);
     events.sort_by_key(|event| event.start_time_sort_key());
-    let events = Events::cloned(events);
+    let events = EventsRef { events };
     Ok(serde_json::to_string(&events)?)
 }
 
 pub async fn index_toml(
-    Extension(events): Extension<Events>,
+    Extension(store): Extension<EventStore>,
     Query(filters): Query<Filters>,
 ) -> Result<String, InternalError> {
+    let events = store.events();
     let mut events = events.matching(&filters);
     events.sort_by_key(|event| event.start_time_sort_key());
-    let events = Events::cloned(events);
-    Ok(toml::to_string(&events)?)
+    let events = EventsRef { events };
+    // Going via a `toml::Value` puts tables such as structured prices after plain values, as TOML
+    // requires, whatever order the fields are declared in.
+    Ok(toml::to_string(&toml::Value::try_from(&events)?)?)
 }
 
 pub async fn index_yaml(
-    Extension(events): Extension<Events>,
+    Extension(store): Extension<EventStore>,
     Query(filters): Query<Filters>,
 ) -> Result<String, InternalError> {
+    let events = store.events();
     let mut events = events.matching(&filters);
     events.sort_by_key(|event| event.start_time_sort_key());
-    let events = Events::cloned(events);
+    let events = EventsRef { events };
     Ok(serde_yaml::to_string(&events)?)
 }
 
+pub async fn index_csv(
+    Extension(store): Extension<EventStore>,
+    Query(filters): Query<Filters>,
+) -> Result<Csv, InternalError> {
+    let events = store.events();
+    let mut events = events.matching(&filters);
+    events.sort_by_key(|event| event.start_time_sort_key());
+    Ok(Csv(events_to_csv(&events).map_err(InternalError::Internal)?))
+}
+
 pub async fn index_ics(
-    Extension(events): Extension<Events>,
+    Extension(store): Extension<EventStore>,
     Query(filters): Query<Filters>,
 ) -> Result<Ics, InternalError> {
+    let events = store.events();
     let mut events = events.matching(&filters);
     events.sort_by_key(|event| event.start_time_sort_key());
     let calendar = events_to_calendar(&events);
@@ -104,29 +122,29 @@ pub async fn index_ics(
 
 #[derive(Template)]
 #[template(path = "index.html")]
-struct IndexTemplate {
+struct IndexTemplate<'a> {
     filters: Filters,
-    months: Vec<Month>,
+    months: Vec<Month<'a>>,
     has_filters: bool,
     countries: Vec<Country>,
     cities: Vec<String>,
     styles: Vec<DanceStyle>,
 }
 
-struct Month {
+struct Month<'a> {
     /// The first day of the month.
     start: NaiveDate,
-    events: Vec<Event>,
+    events: Vec<&'a Event>,
 }
 
-impl Month {
+impl Month<'_> {
     pub fn name(&self) -> String {
         self.start.format("%B %Y").to_string()
     }
 }
 
 /// Given a list of events in arbitrary order, sort them in ascending order of start date, then group them by starting month.
-fn sort_and_group_by_month(mut events: Vec<&Event>) -> Vec<Month> {
+fn sort_and_group_by_month(mut events: Vec<&Event>) -> Vec<Month<'_>> {
     events.sort_by_key(|event| event.start_time_sort_key());
 
     let mut months = vec![];
@@ -136,14 +154,14 @@ fn sort_and_group_by_month(mut events: Vec<&Event>) -> Vec<Month> {
     };
     for event in events {
         if event.start_year() == month.start.year() && event.start_month() == month.start.month() {
-            month.events.push(event.to_owned());
+            month.events.push(event);
         } else {
             if !month.events.is_empty() {
                 months.push(month);
             }
             month = Month {
                 start: NaiveDate::from_ymd(event.start_year(), event.start_month(), 1),
-                events: vec![event.to_owned()],
+                events: vec![event],
             };
         }
     }
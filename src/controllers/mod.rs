@@ -12,8 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod admin;
 pub mod bands;
 pub mod callers;
 pub mod cities;
+pub mod event;
 pub mod index;
 pub mod organisations;
@@ -12,13 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{errors::InternalError, model::events::Events};
+use crate::{errors::InternalError, store::EventStore};
 use askama::Template;
 use axum::{extract::Extension, response::Html};
 
 pub async fn organisations(
-    Extension(events): Extension<Events>,
+    Extension(store): Extension<EventStore>,
 ) -> Result<Html<String>, InternalError> {
+    let events = store.events();
     let organisations = events.organisations();
     let template = OrganisationsTemplate { organisations };
     Ok(Html(template.render()?))
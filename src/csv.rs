@@ -0,0 +1,504 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::{
+    dancestyle::DanceStyle,
+    event::{Event, EventTime, Venue},
+    price::Price,
+};
+use axum::{
+    body::{boxed, Full},
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use eyre::{bail, eyre, Report};
+use serde::{
+    de::{value, IntoDeserializer},
+    Deserialize,
+};
+use std::collections::HashMap;
+
+/// The columns of the CSV representation of events, in the order in which they are written.
+const COLUMNS: [&str; 27] = [
+    "id",
+    "name",
+    "details",
+    "links",
+    "start",
+    "end",
+    "timezone",
+    "country",
+    "city",
+    "region",
+    "styles",
+    "workshop",
+    "social",
+    "bands",
+    "callers",
+    "teachers",
+    "price",
+    "organisation",
+    "cancelled",
+    "tentative",
+    "venue_name",
+    "venue_street",
+    "venue_postcode",
+    "venue_city",
+    "venue_country",
+    "venue_latitude",
+    "venue_longitude",
+];
+
+/// The columns which must be present when reading events from CSV.
+const REQUIRED_COLUMNS: [&str; 4] = ["name", "start", "country", "city"];
+
+/// The separator used to join the items of list fields such as links, styles and bands. Items are
+/// trimmed when reading, so a separator without the space is also accepted. A semicolon or
+/// backslash within an item is escaped with a backslash.
+const LIST_SEPARATOR: &str = "; ";
+
+/// Formats in which local times without a UTC offset may be given, in the event's timezone.
+const LOCAL_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+];
+
+/// Converts the given events to CSV, with a header row. Recurrence rules, schedules and band
+/// details aren't included, so recurring events should be expanded first.
+pub fn events_to_csv(events: &[&Event]) -> Result<String, Report> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(COLUMNS)?;
+    for event in events {
+        writer.write_record(event_to_record(event))?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+fn event_to_record(event: &Event) -> Vec<String> {
+    let (start, end) = match &event.time {
+        EventTime::DateOnly {
+            start_date,
+            end_date,
+        } => (start_date.to_string(), end_date.to_string()),
+        EventTime::DateTime { start, end } => (start.to_rfc3339(), end.to_rfc3339()),
+    };
+    let venue = event.venue.clone().unwrap_or_default();
+    vec![
+        event.id(),
+        event.name.clone(),
+        event.details.clone().unwrap_or_default(),
+        join_list(&event.links),
+        start,
+        end,
+        event
+            .timezone
+            .map(|timezone| timezone.name().to_owned())
+            .unwrap_or_default(),
+        event.country.clone(),
+        event.city.clone(),
+        event.region.clone().unwrap_or_default(),
+        join_list(event.styles.iter().map(|style| style.tag())),
+        event.workshop.to_string(),
+        event.social.to_string(),
+        join_list(&event.bands),
+        join_list(&event.callers),
+        join_list(&event.teachers),
+        event
+            .price
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default(),
+        event.organisation.clone().unwrap_or_default(),
+        event.cancelled.to_string(),
+        event.tentative.to_string(),
+        venue.name.unwrap_or_default(),
+        venue.street.unwrap_or_default(),
+        venue.postcode.unwrap_or_default(),
+        venue.city.unwrap_or_default(),
+        venue.country.unwrap_or_default(),
+        venue
+            .latitude
+            .map(|latitude| latitude.to_string())
+            .unwrap_or_default(),
+        venue
+            .longitude
+            .map(|longitude| longitude.to_string())
+            .unwrap_or_default(),
+    ]
+}
+
+/// A row of a CSV file read by `parse_events`.
+#[derive(Debug)]
+pub struct CsvRow {
+    /// The line number of the row in the file, starting from 1 for the header.
+    pub line: u64,
+    /// The name of the event, or an empty string if it has none.
+    pub name: String,
+    /// The event, or the reason why the row couldn't be converted to one.
+    pub event: Result<Event, Report>,
+}
+
+/// Parses events from CSV with a header row. The columns may be in any order, and only the name,
+/// start, country and city columns are required. Unlike `events_to_csv`, this also accepts local
+/// times without a UTC offset, which are taken to be in the event's timezone, and "yes" or "no"
+/// for boolean fields.
+///
+/// Returns an error if the header is invalid, or otherwise a result for each row.
+pub fn parse_events(csv: &str) -> Result<Vec<CsvRow>, Report> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(csv.as_bytes());
+    let mut columns = HashMap::new();
+    for (index, column) in reader.headers()?.iter().enumerate() {
+        let column = column.trim();
+        if !COLUMNS.contains(&column) {
+            bail!("Unknown column '{}'", column);
+        }
+        columns.insert(column.to_owned(), index);
+    }
+    for column in REQUIRED_COLUMNS {
+        if !columns.contains_key(column) {
+            bail!("Missing column '{}'", column);
+        }
+    }
+
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let row = Row {
+            columns: &columns,
+            record: &record,
+        };
+        rows.push(CsvRow {
+            line: record.position().map_or(0, |position| position.line()),
+            name: row.field("name").unwrap_or_default().to_owned(),
+            event: row.to_event(),
+        });
+    }
+    Ok(rows)
+}
+
+/// A row of a CSV file, along with the indices of the columns.
+struct Row<'a> {
+    columns: &'a HashMap<String, usize>,
+    record: &'a csv::StringRecord,
+}
+
+impl Row<'_> {
+    /// Gets the trimmed value of the given column, if it is present and not empty.
+    fn field(&self, column: &str) -> Option<&str> {
+        let index = *self.columns.get(column)?;
+        Some(self.record.get(index)?.trim()).filter(|value| !value.is_empty())
+    }
+
+    fn string(&self, column: &str) -> Option<String> {
+        self.field(column).map(ToOwned::to_owned)
+    }
+
+    fn required(&self, column: &str) -> Result<String, Report> {
+        self.string(column)
+            .ok_or_else(|| eyre!("Missing {}", column))
+    }
+
+    fn list(&self, column: &str) -> Vec<String> {
+        self.field(column).map(split_list).unwrap_or_default()
+    }
+
+    fn bool(&self, column: &str) -> Result<bool, Report> {
+        match self.field(column).map(str::to_lowercase).as_deref() {
+            None | Some("false") | Some("no") | Some("0") => Ok(false),
+            Some("true") | Some("yes") | Some("1") => Ok(true),
+            Some(value) => bail!("Invalid {} '{}'", column, value),
+        }
+    }
+
+    fn float(&self, column: &str) -> Result<Option<f64>, Report> {
+        self.field(column)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| eyre!("Invalid {} '{}'", column, value))
+            })
+            .transpose()
+    }
+
+    fn to_event(&self) -> Result<Event, Report> {
+        let timezone: Option<Tz> = self
+            .field("timezone")
+            .map(|timezone| {
+                timezone
+                    .parse()
+                    .map_err(|_| eyre!("Unknown timezone '{}'", timezone))
+            })
+            .transpose()?;
+        let start = self.required("start")?;
+        let end = self.string("end").unwrap_or_else(|| start.clone());
+        let time = if let (Ok(start_date), Ok(end_date)) = (
+            NaiveDate::parse_from_str(&start, "%Y-%m-%d"),
+            NaiveDate::parse_from_str(&end, "%Y-%m-%d"),
+        ) {
+            EventTime::DateOnly {
+                start_date,
+                end_date,
+            }
+        } else {
+            EventTime::DateTime {
+                start: parse_date_time(&start, timezone)?,
+                end: parse_date_time(&end, timezone)?,
+            }
+        };
+        let styles = self
+            .list("styles")
+            .iter()
+            .map(|style| {
+                DanceStyle::deserialize(style.as_str().into_deserializer())
+                    .map_err(|_: value::Error| eyre!("Unknown style '{}'", style))
+            })
+            .collect::<Result<_, _>>()?;
+        let venue = Venue {
+            name: self.string("venue_name"),
+            street: self.string("venue_street"),
+            postcode: self.string("venue_postcode"),
+            city: self.string("venue_city"),
+            country: self.string("venue_country"),
+            latitude: self.float("venue_latitude")?,
+            longitude: self.float("venue_longitude")?,
+        };
+
+        let mut event = Event {
+            id: None,
+            name: self.required("name")?,
+            details: self.string("details"),
+            links: self.list("links"),
+            time,
+            timezone,
+            country: self.required("country")?,
+            city: self.required("city")?,
+            region: self.string("region"),
+            styles,
+            workshop: self.bool("workshop")?,
+            social: self.bool("social")?,
+            bands: self.list("bands"),
+            callers: self.list("callers"),
+            teachers: self.list("teachers"),
+            price: self.field("price").map(Price::parse),
+            organisation: self.string("organisation"),
+            cancelled: self.bool("cancelled")?,
+            tentative: self.bool("tentative")?,
+            venue: if venue == Venue::default() {
+                None
+            } else {
+                Some(venue)
+            },
+            schedule: vec![],
+            band_details: vec![],
+            recurrence: None,
+            source: None,
+        };
+        // Derived IDs are exported too, but only need to be kept if they are different.
+        if let Some(id) = self.string("id") {
+            if id != event.id() {
+                event.id = Some(id);
+            }
+        }
+        Ok(event)
+    }
+}
+
+/// Joins the items of a list field, escaping any semicolons and backslashes within them.
+fn join_list(items: impl IntoIterator<Item = impl AsRef<str>>) -> String {
+    items
+        .into_iter()
+        .map(|item| item.as_ref().replace('\\', "\\\\").replace(';', "\\;"))
+        .collect::<Vec<_>>()
+        .join(LIST_SEPARATOR)
+}
+
+/// Splits a list field into its trimmed, non-empty items, unescaping semicolons and backslashes.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = vec![];
+    let mut item = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => item.extend(chars.next()),
+            ';' => items.push(std::mem::take(&mut item)),
+            _ => item.push(c),
+        }
+    }
+    items.push(item);
+    items
+        .iter()
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Parses a time with a UTC offset, or a local time in the given timezone.
+fn parse_date_time(value: &str, timezone: Option<Tz>) -> Result<DateTime<FixedOffset>, Report> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time);
+    }
+    let local = LOCAL_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .ok_or_else(|| eyre!("Invalid time '{}'", value))?;
+    let timezone = timezone.ok_or_else(|| eyre!("Local time '{}' without a timezone", value))?;
+    let time = timezone
+        .from_local_datetime(&local)
+        .single()
+        .ok_or_else(|| eyre!("Ambiguous or invalid local time '{}'", value))?;
+    Ok(time.with_timezone(&time.offset().fix()))
+}
+
+pub struct Csv(pub String);
+
+impl IntoResponse for Csv {
+    fn into_response(self) -> Response {
+        let mut res = Response::new(boxed(Full::from(self.0)));
+        res.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/csv; charset=utf-8"),
+        );
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::price::PriceKind;
+    use chrono_tz::Europe::London;
+
+    fn event() -> Event {
+        Event {
+            name: "Ceilidh, with \"quotes\"".to_string(),
+            details: Some("First line\nSecond line".to_string()),
+            links: vec![
+                "https://example.com/ceilidh".to_string(),
+                "https://example.com/events;id=123".to_string(),
+            ],
+            time: EventTime::DateTime {
+                start: FixedOffset::east(3600).ymd(2030, 6, 1).and_hms(19, 30, 0),
+                end: FixedOffset::east(3600).ymd(2030, 6, 1).and_hms(23, 0, 0),
+            },
+            timezone: Some(London),
+            country: "UK".to_string(),
+            city: "Oxford".to_string(),
+            styles: vec![DanceStyle::EnglishCeilidh, DanceStyle::Contra],
+            bands: vec!["Band A; the sequel".to_string(), "Band B\\".to_string()],
+            callers: vec!["Caller".to_string()],
+            price: Some(Price {
+                currency: Some("GBP".to_string()),
+                min: Some(8.0),
+                max: Some(12.5),
+                ..Default::default()
+            }),
+            organisation: Some("Folk Club".to_string()),
+            venue: Some(Venue {
+                name: Some("Village Hall".to_string()),
+                latitude: Some(51.75),
+                longitude: Some(-1.25),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let csv = events_to_csv(&[&event()]).unwrap();
+        let rows = parse_events(&csv).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(rows[0].event.as_ref().unwrap(), &event());
+        assert!(csv.contains(&event().id()));
+
+        let explicit = Event {
+            id: Some("ceilidh".to_string()),
+            ..event()
+        };
+        let csv = events_to_csv(&[&explicit]).unwrap();
+        let rows = parse_events(&csv).unwrap();
+        assert_eq!(rows[0].event.as_ref().unwrap(), &explicit);
+    }
+
+    #[test]
+    fn escaped_lists() {
+        assert_eq!(
+            split_list(r"a\; b;c \\; ;d\"),
+            vec!["a; b".to_string(), "c \\".to_string(), "d".to_string()]
+        );
+        assert_eq!(join_list(["a; b", "c \\"]), r"a\; b; c \\");
+    }
+
+    #[test]
+    fn parse_spreadsheet() {
+        let csv = "\
+name,city,country,start,end,timezone,styles,workshop,social,price
+Bal,Gent,Belgium,2030-03-01,,,balfolk,,yes,free
+Ceilidh,Oxford,UK,2030-03-01 19:30,2030-03-01 23:00,Europe/London,e-ceilidh;contra,no,yes,£8
+Broken,Oxford,UK,2030-03-01 19:30,,,contra,,,
+";
+        let rows = parse_events(csv).unwrap();
+        assert_eq!(rows.len(), 3);
+
+        let bal = rows[0].event.as_ref().unwrap();
+        assert_eq!(
+            bal.time,
+            EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd(2030, 3, 1),
+                end_date: NaiveDate::from_ymd(2030, 3, 1),
+            }
+        );
+        assert_eq!(bal.styles, vec![DanceStyle::Balfolk]);
+        assert_eq!(bal.price.as_ref().unwrap().kind, PriceKind::Free);
+        assert!(bal.social);
+
+        let ceilidh = rows[1].event.as_ref().unwrap();
+        assert_eq!(
+            ceilidh.time,
+            EventTime::DateTime {
+                start: FixedOffset::east(0).ymd(2030, 3, 1).and_hms(19, 30, 0),
+                end: FixedOffset::east(0).ymd(2030, 3, 1).and_hms(23, 0, 0),
+            }
+        );
+        assert_eq!(
+            ceilidh.styles,
+            vec![DanceStyle::EnglishCeilidh, DanceStyle::Contra]
+        );
+
+        assert_eq!(rows[2].line, 4);
+        assert_eq!(rows[2].name, "Broken");
+        assert_eq!(
+            rows[2].event.as_ref().unwrap_err().to_string(),
+            "Local time '2030-03-01 19:30' without a timezone"
+        );
+    }
+
+    #[test]
+    fn unknown_column() {
+        assert_eq!(
+            parse_events("name,start,country,city,colour\n")
+                .unwrap_err()
+                .to_string(),
+            "Unknown column 'colour'"
+        );
+    }
+}
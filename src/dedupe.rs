@@ -0,0 +1,394 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::event::Event;
+use chrono::NaiveDate;
+use std::collections::{HashMap, HashSet};
+
+/// Events whose names are at least this similar, on a scale from 0 to 1, may be duplicates.
+const NAME_SIMILARITY_THRESHOLD: f64 = 0.7;
+
+/// A pair of events which are likely to be duplicates of each other.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Duplicate {
+    /// The index of the first event of the pair.
+    pub first: usize,
+    /// The index of the second event of the pair, which comes after the first.
+    pub second: usize,
+    /// Why the events are thought to be duplicates.
+    pub reasons: Vec<String>,
+}
+
+/// Finds pairs of events which are likely to be duplicates. Events must have overlapping dates, and
+/// either share a link or upstream source, or be in the same city with similar names. Recurring
+/// events are only compared with other recurring events, which must repeat in the same way and have
+/// at least one occurrence on the same date.
+pub fn find_duplicates(events: &[&Event]) -> Vec<Duplicate> {
+    // Group events into candidate pairs by city and by link, so that not every pair of events needs
+    // to be compared.
+    let mut by_city: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    let mut by_link: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut by_source: HashMap<(&str, &str), Vec<usize>> = HashMap::new();
+    for (index, event) in events.iter().enumerate() {
+        by_city
+            .entry((normalise(&event.country), normalise(&event.city)))
+            .or_default()
+            .push(index);
+        for link in &event.links {
+            by_link.entry(link).or_default().push(index);
+        }
+        if let Some(source) = &event.source {
+            by_source
+                .entry((&source.importer, &source.id))
+                .or_default()
+                .push(index);
+        }
+    }
+    let mut candidates = HashSet::new();
+    for indices in by_city
+        .values()
+        .chain(by_link.values())
+        .chain(by_source.values())
+    {
+        for (i, &first) in indices.iter().enumerate() {
+            for &second in &indices[i + 1..] {
+                if second != first {
+                    candidates.insert((first.min(second), first.max(second)));
+                }
+            }
+        }
+    }
+
+    let mut duplicates: Vec<Duplicate> = candidates
+        .into_iter()
+        .filter_map(|(first, second)| {
+            let reasons = duplicate_reasons(events[first], events[second])?;
+            Some(Duplicate {
+                first,
+                second,
+                reasons,
+            })
+        })
+        .collect();
+    duplicates.sort_by_key(|duplicate| (duplicate.first, duplicate.second));
+    duplicates
+}
+
+/// Groups the given pairs of duplicates into sets of events which are all directly duplicates of
+/// each other. An event is only added to a group if it was found to be a duplicate of every event
+/// already in the group, so events which are each similar to a third event but not to each other
+/// aren't grouped together. Each group is in order of index.
+pub fn group_duplicates(duplicates: &[Duplicate]) -> Vec<Vec<usize>> {
+    let pairs: HashSet<(usize, usize)> = duplicates
+        .iter()
+        .map(|duplicate| (duplicate.first, duplicate.second))
+        .collect();
+    let is_duplicate = |a: usize, b: usize| pairs.contains(&(a.min(b), a.max(b)));
+
+    let mut groups: Vec<Vec<usize>> = vec![];
+    for duplicate in duplicates {
+        let first_group = groups
+            .iter()
+            .position(|group| group.contains(&duplicate.first));
+        let second_group = groups
+            .iter()
+            .position(|group| group.contains(&duplicate.second));
+        match (first_group, second_group) {
+            (None, None) => groups.push(vec![duplicate.first, duplicate.second]),
+            (Some(group), None) | (None, Some(group)) => {
+                let new = if first_group.is_some() {
+                    duplicate.second
+                } else {
+                    duplicate.first
+                };
+                if groups[group].iter().all(|&other| is_duplicate(other, new)) {
+                    groups[group].push(new);
+                }
+            }
+            (Some(_), Some(_)) => {}
+        }
+    }
+    for group in &mut groups {
+        group.sort_unstable();
+    }
+    groups.sort();
+    groups
+}
+
+/// Combines each group of duplicates into a single event. Returns an entry for each of the given
+/// events: the combined event in place of the first event of each group, `None` for the other
+/// events of the group, and other events unchanged.
+///
+/// Groups which can't be combined, because their events are in different places, at different
+/// times or have conflicting details, are left as separate events and returned as the second
+/// element.
+pub fn merge_duplicates(
+    events: &[&Event],
+    groups: &[Vec<usize>],
+) -> (Vec<Option<Event>>, Vec<Vec<usize>>) {
+    let mut merged: Vec<Option<Event>> = events.iter().map(|&event| Some(event.clone())).collect();
+    let mut unmergeable = vec![];
+    for group in groups {
+        let first = events[group[0]];
+        let combined = group[1..]
+            .iter()
+            .try_fold(first.clone(), |combined, &index| {
+                if same_place_and_time(first, events[index]) {
+                    combined.combine(events[index])
+                } else {
+                    None
+                }
+            });
+        if let Some(combined) = combined {
+            merged[group[0]] = Some(combined);
+            for &index in &group[1..] {
+                merged[index] = None;
+            }
+        } else {
+            unmergeable.push(group.clone());
+        }
+    }
+    (merged, unmergeable)
+}
+
+/// Returns whether the two events are in the same city and country at exactly the same time, so
+/// that one can be merged into the other without losing anything.
+fn same_place_and_time(a: &Event, b: &Event) -> bool {
+    normalise(&a.country) == normalise(&b.country)
+        && normalise(&a.city) == normalise(&b.city)
+        && a.time == b.time
+}
+
+/// Returns the reasons why the two events are likely to be duplicates, or `None` if they probably
+/// aren't.
+fn duplicate_reasons(a: &Event, b: &Event) -> Option<Vec<String>> {
+    match (&a.recurrence, &b.recurrence) {
+        (None, None) => {
+            if a.start_date() > b.end_date() || b.start_date() > a.end_date() {
+                return None;
+            }
+        }
+        (Some(a_recurrence), Some(b_recurrence)) => {
+            if a_recurrence.frequency != b_recurrence.frequency
+                || a_recurrence.interval != b_recurrence.interval
+            {
+                return None;
+            }
+            let a_dates: HashSet<NaiveDate> =
+                a_recurrence.dates(a.start_date()).into_iter().collect();
+            if !b_recurrence
+                .dates(b.start_date())
+                .iter()
+                .any(|date| a_dates.contains(date))
+            {
+                return None;
+            }
+        }
+        _ => return None,
+    }
+
+    let mut reasons = vec![];
+    let shared_links: Vec<&String> = a
+        .links
+        .iter()
+        .filter(|link| b.links.contains(link))
+        .collect();
+    for link in &shared_links {
+        reasons.push(format!("shared link {}", link));
+    }
+    let same_source = match (&a.source, &b.source) {
+        (Some(a_source), Some(b_source)) => a_source.same_upstream(b_source),
+        _ => false,
+    };
+    if same_source {
+        reasons.push("same upstream source".to_string());
+    }
+    let same_city =
+        normalise(&a.country) == normalise(&b.country) && normalise(&a.city) == normalise(&b.city);
+    let similarity = name_similarity(&a.name, &b.name);
+    if shared_links.is_empty()
+        && !same_source
+        && !(same_city && similarity >= NAME_SIMILARITY_THRESHOLD)
+    {
+        return None;
+    }
+
+    if similarity >= NAME_SIMILARITY_THRESHOLD {
+        reasons.push(format!("{:.0}% similar names", similarity * 100.0));
+    }
+    if same_city {
+        reasons.push("same city".to_string());
+    }
+    if a.recurrence.is_some() {
+        reasons.push("same recurrence".to_string());
+    }
+    reasons.push(if a.time == b.time {
+        "same time".to_string()
+    } else if a.recurrence.is_some() {
+        "shared occurrences".to_string()
+    } else {
+        "overlapping dates".to_string()
+    });
+    Some(reasons)
+}
+
+/// Returns how similar the two names are, from 0 for completely different to 1 for the same,
+/// ignoring case, punctuation and whitespace. This is the Sørensen–Dice coefficient of the sets of
+/// character bigrams of the names.
+fn name_similarity(a: &str, b: &str) -> f64 {
+    let a = bigrams(a);
+    let b = bigrams(b);
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let shared = a.intersection(&b).count();
+    2.0 * shared as f64 / (a.len() + b.len()) as f64
+}
+
+/// Gets the set of pairs of adjacent alphanumeric characters in each word of the given string,
+/// ignoring case.
+fn bigrams(s: &str) -> HashSet<(char, char)> {
+    let mut bigrams = HashSet::new();
+    for word in s
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let chars: Vec<char> = word.chars().collect();
+        if chars.len() == 1 {
+            bigrams.insert((chars[0], ' '));
+        }
+        for pair in chars.windows(2) {
+            bigrams.insert((pair[0], pair[1]));
+        }
+    }
+    bigrams
+}
+
+/// Normalises a name for comparison, ignoring case and whitespace differences.
+fn normalise(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        event::EventTime,
+        recurrence::{Frequency, Recurrence},
+    };
+
+    fn event(name: &str, day: u32, city: &str, links: &[&str]) -> Event {
+        Event {
+            name: name.to_string(),
+            links: links.iter().map(ToString::to_string).collect(),
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd(2022, 3, day),
+                end_date: NaiveDate::from_ymd(2022, 3, day),
+            },
+            city: city.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn similarity() {
+        assert_eq!(name_similarity("Bal in Gent", "bal in gent!"), 1.0);
+        assert!(name_similarity("Balfolk in Gent", "Bal folk in Gent") >= 0.7);
+        assert!(name_similarity("Balfolk in Gent", "Contra in Leuven") < 0.7);
+    }
+
+    #[test]
+    fn find_and_merge() {
+        let events = [
+            event("Balfolk in Gent", 1, "Gent", &["https://a.example/"]),
+            event("Bal folk in Gent", 1, "Gent", &["https://b.example/"]),
+            event("Balfolk in Gent", 2, "Gent", &[]),
+            event("Something else", 1, "Brugge", &["https://b.example/"]),
+            event("Contra", 1, "Gent", &[]),
+        ];
+        let event_refs: Vec<&Event> = events.iter().collect();
+        let duplicates = find_duplicates(&event_refs);
+        assert_eq!(
+            duplicates
+                .iter()
+                .map(|duplicate| (duplicate.first, duplicate.second))
+                .collect::<Vec<_>>(),
+            vec![(0, 1), (1, 3)]
+        );
+        assert_eq!(
+            duplicates[1].reasons,
+            vec!["shared link https://b.example/", "same time"]
+        );
+
+        // "Something else" shares a link with "Bal folk in Gent" but isn't similar to "Balfolk in
+        // Gent", so isn't grouped with them.
+        let groups = group_duplicates(&duplicates);
+        assert_eq!(groups, vec![vec![0, 1]]);
+        let (merged, unmergeable) = merge_duplicates(&event_refs, &groups);
+        assert!(unmergeable.is_empty());
+        let merged: Vec<Event> = merged.into_iter().flatten().collect();
+        assert_eq!(merged.len(), 4);
+        assert_eq!(merged[0].name, "Balfolk in Gent");
+        assert_eq!(
+            merged[0].links,
+            vec!["https://a.example/", "https://b.example/"]
+        );
+        assert_eq!(merged[2].name, "Something else");
+
+        // Events in different cities are reported rather than merged.
+        let (merged, unmergeable) = merge_duplicates(&event_refs, &[vec![1, 3]]);
+        assert_eq!(unmergeable, vec![vec![1, 3]]);
+        assert_eq!(merged.into_iter().flatten().count(), 5);
+    }
+
+    #[test]
+    fn recurring() {
+        let weekly = |name: &str, day: u32, frequency: Frequency| Event {
+            recurrence: Some(Recurrence {
+                frequency,
+                interval: 1,
+                until: NaiveDate::from_ymd(2022, 6, 30),
+                except: vec![],
+                overrides: vec![],
+            }),
+            ..event(name, day, "Gent", &[])
+        };
+        let events = [
+            weekly("Weekly bal", 1, Frequency::Weekly),
+            weekly("Weekly bal!", 8, Frequency::Weekly),
+            // Not compared with the recurring events, even though it is on one of their dates.
+            event("Weekly bal", 8, "Gent", &[]),
+            weekly("Weekly bal", 2, Frequency::Weekly),
+            weekly("Weekly bal", 1, Frequency::Monthly),
+        ];
+        let event_refs: Vec<&Event> = events.iter().collect();
+        assert_eq!(
+            find_duplicates(&event_refs),
+            vec![Duplicate {
+                first: 0,
+                second: 1,
+                reasons: vec![
+                    "100% similar names".to_string(),
+                    "same city".to_string(),
+                    "same recurrence".to_string(),
+                    "shared occurrences".to_string(),
+                ],
+            }]
+        );
+    }
+}
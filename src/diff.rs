@@ -0,0 +1,328 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::{event::Event, events::Events};
+use eyre::Report;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+};
+
+/// The differences between two sets of events.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct EventsDiff<'a> {
+    /// Events which are only in the new set.
+    pub added: Vec<&'a Event>,
+    /// Events which are only in the old set.
+    pub removed: Vec<&'a Event>,
+    /// Events which are in both sets but have changed.
+    pub changed: Vec<ChangedEvent<'a>>,
+    /// The number of events which are the same in both sets.
+    pub unchanged: usize,
+}
+
+/// An event which is in both sets, but with some differences.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ChangedEvent<'a> {
+    #[serde(skip)]
+    pub old: &'a Event,
+    #[serde(skip)]
+    pub new: &'a Event,
+    /// The ID of the event in the new set.
+    pub id: String,
+    pub changes: Vec<FieldChange>,
+}
+
+/// A change to a single field of an event.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    /// The old value of the field, or `None` if it wasn't set.
+    pub old: Option<Value>,
+    /// The new value of the field, or `None` if it is no longer set.
+    pub new: Option<Value>,
+}
+
+impl<'a> EventsDiff<'a> {
+    /// Compares the two sets of events. Events are matched first if they are identical, then if they
+    /// have the same name, start date and city, and finally if they share a link which no other
+    /// event has.
+    pub fn compare(old: &'a Events, new: &'a Events) -> Result<Self, Report> {
+        let mut old_unmatched: Vec<Option<&Event>> = old.events.iter().map(Some).collect();
+        let mut new_unmatched: Vec<Option<&Event>> = new.events.iter().map(Some).collect();
+        let mut pairs = vec![];
+
+        match_events(
+            &mut old_unmatched,
+            &mut new_unmatched,
+            &mut pairs,
+            |event| vec![serde_json::to_string(event).unwrap()],
+        );
+        match_events(
+            &mut old_unmatched,
+            &mut new_unmatched,
+            &mut pairs,
+            |event| {
+                vec![format!(
+                    "{}\n{}\n{}",
+                    event.name.to_lowercase(),
+                    event.start_date(),
+                    event.city.to_lowercase()
+                )]
+            },
+        );
+        let old_links = link_counts(&old.events);
+        let new_links = link_counts(&new.events);
+        match_events(
+            &mut old_unmatched,
+            &mut new_unmatched,
+            &mut pairs,
+            |event| {
+                event
+                    .links
+                    .iter()
+                    .filter(|link| {
+                        old_links.get(link.as_str()) == Some(&1)
+                            && new_links.get(link.as_str()) == Some(&1)
+                    })
+                    .cloned()
+                    .collect()
+            },
+        );
+
+        let mut diff = Self {
+            added: new_unmatched.into_iter().flatten().collect(),
+            removed: old_unmatched.into_iter().flatten().collect(),
+            ..Default::default()
+        };
+        for (old, new) in pairs {
+            let changes = field_changes(old, new)?;
+            if changes.is_empty() {
+                diff.unchanged += 1;
+            } else {
+                diff.changed.push(ChangedEvent {
+                    old,
+                    new,
+                    id: new.id(),
+                    changes,
+                });
+            }
+        }
+        diff.changed
+            .sort_by_key(|changed| changed.new.start_time_sort_key());
+        Ok(diff)
+    }
+
+    /// Returns whether there are any differences.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl Display for EventsDiff<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for event in &self.removed {
+            writeln!(f, "Removed: {}", describe(event))?;
+        }
+        for event in &self.added {
+            writeln!(f, "Added: {}", describe(event))?;
+        }
+        for changed in &self.changed {
+            writeln!(f, "Changed: {}", describe(changed.new))?;
+            for change in &changed.changes {
+                writeln!(
+                    f,
+                    "  {}: {} -> {}",
+                    change.field,
+                    format_value(&change.old),
+                    format_value(&change.new)
+                )?;
+            }
+        }
+        writeln!(
+            f,
+            "{} added, {} removed, {} changed, {} unchanged.",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len(),
+            self.unchanged
+        )
+    }
+}
+
+/// Matches up unmatched events from the old and new sets which have any key in common, adding them
+/// to `pairs` and removing them from the unmatched lists.
+fn match_events<'a>(
+    old_unmatched: &mut [Option<&'a Event>],
+    new_unmatched: &mut [Option<&'a Event>],
+    pairs: &mut Vec<(&'a Event, &'a Event)>,
+    keys: impl Fn(&Event) -> Vec<String>,
+) {
+    let mut old_by_key: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, event) in old_unmatched.iter().enumerate() {
+        if let Some(event) = event {
+            for key in keys(event) {
+                old_by_key.entry(key).or_default().push(index);
+            }
+        }
+    }
+    for new_slot in new_unmatched.iter_mut() {
+        let new_event = if let Some(new_event) = new_slot {
+            *new_event
+        } else {
+            continue;
+        };
+        let old_index = keys(new_event)
+            .iter()
+            .filter_map(|key| old_by_key.get(key))
+            .flatten()
+            .copied()
+            .find(|&index| old_unmatched[index].is_some());
+        if let Some(old_index) = old_index {
+            pairs.push((old_unmatched[old_index].take().unwrap(), new_event));
+            *new_slot = None;
+        }
+    }
+}
+
+/// Counts how many of the given events have each link.
+fn link_counts(events: &[Event]) -> HashMap<&str, usize> {
+    let mut counts = HashMap::new();
+    for event in events {
+        for link in &event.links {
+            *counts.entry(link.as_str()).or_default() += 1;
+        }
+    }
+    counts
+}
+
+/// Finds the fields which differ between the two events, in the order they are defined.
+fn field_changes(old: &Event, new: &Event) -> Result<Vec<FieldChange>, Report> {
+    let old_fields = serde_yaml::to_value(old)?;
+    let new_fields = serde_yaml::to_value(new)?;
+    let (old_fields, new_fields) = match (old_fields.as_mapping(), new_fields.as_mapping()) {
+        (Some(old_fields), Some(new_fields)) => (old_fields, new_fields),
+        _ => return Ok(vec![]),
+    };
+
+    let mut names = vec![];
+    for name in old_fields
+        .iter()
+        .chain(new_fields.iter())
+        .map(|(key, _)| key)
+    {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    let mut changes = vec![];
+    for name in names {
+        let old_value = old_fields.get(name);
+        let new_value = new_fields.get(name);
+        if old_value != new_value {
+            changes.push(FieldChange {
+                field: name.as_str().unwrap_or_default().to_owned(),
+                old: old_value.map(serde_json::to_value).transpose()?,
+                new: new_value.map(serde_json::to_value).transpose()?,
+            });
+        }
+    }
+    Ok(changes)
+}
+
+/// Describes an event on a single line.
+fn describe(event: &Event) -> String {
+    format!(
+        "'{}' on {} in {}, {}",
+        event.name,
+        event.start_date(),
+        event.city,
+        event.country
+    )
+}
+
+fn format_value(value: &Option<Value>) -> String {
+    match value {
+        None => "(none)".to_string(),
+        Some(value) => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{dancestyle::DanceStyle, event::EventTime};
+    use chrono::NaiveDate;
+
+    fn event(name: &str, day: u32, links: &[&str]) -> Event {
+        Event {
+            name: name.to_string(),
+            links: links.iter().map(ToString::to_string).collect(),
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd(2022, 3, day),
+                end_date: NaiveDate::from_ymd(2022, 3, day),
+            },
+            country: "UK".to_string(),
+            city: "London".to_string(),
+            styles: vec![DanceStyle::Contra],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn added_removed_changed() {
+        let old = Events {
+            events: vec![
+                event("Same", 1, &[]),
+                event("Renamed", 2, &["https://example.com/2"]),
+                event("Removed", 3, &[]),
+                event("Band added", 4, &[]),
+            ],
+        };
+        let mut band_added = event("Band added", 4, &[]);
+        band_added.bands = vec!["Band".to_string()];
+        let new = Events {
+            events: vec![
+                event("Added", 5, &[]),
+                band_added,
+                event("Same", 1, &[]),
+                event("New name", 2, &["https://example.com/2"]),
+            ],
+        };
+
+        let diff = EventsDiff::compare(&old, &new).unwrap();
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.added, vec![&new.events[0]]);
+        assert_eq!(diff.removed, vec![&old.events[2]]);
+        assert_eq!(diff.changed.len(), 2);
+        assert_eq!(
+            diff.changed[0].changes,
+            vec![FieldChange {
+                field: "name".to_string(),
+                old: Some(Value::String("Renamed".to_string())),
+                new: Some(Value::String("New name".to_string())),
+            }]
+        );
+        assert_eq!(
+            diff.changed[1].changes,
+            vec![FieldChange {
+                field: "bands".to_string(),
+                old: None,
+                new: Some(serde_json::json!(["Band"])),
+            }]
+        );
+    }
+}
@@ -0,0 +1,363 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{
+    model::{event::Event, events::Events},
+    validate::event_positions,
+};
+use eyre::Report;
+
+/// The start of the comment which tells editors which schema to use for an events file.
+const SCHEMA_HEADER_PREFIX: &str = "# yaml-language-server: $schema=";
+
+/// The schema header for events files in a directory directly under the root of the data
+/// repository.
+const DEFAULT_SCHEMA_HEADER: &str = "# yaml-language-server: $schema=../events_schema.json";
+
+/// Formats the given events as YAML for an events file, with a reference to the schema.
+pub fn events_yaml(events: &Events) -> Result<String, Report> {
+    let yaml = serde_yaml::to_string(events)?;
+    Ok(yaml.replacen("---", DEFAULT_SCHEMA_HEADER, 1))
+}
+
+/// Rewrites the contents of an events file in canonical form, with events sorted by start time and
+/// fields in a consistent order and style.
+///
+/// Full-line comments are kept with the event which follows them, or at the start or end of the
+/// file if they don't come before any event. Comments at the end of a line are moved onto their own
+/// line before the event. Multi-line strings are written as literal block scalars where possible.
+pub fn format_events_file(contents: &str) -> Result<String, Report> {
+    let events: Events = serde_yaml::from_str(contents)?;
+    let origins: Vec<Option<usize>> = (0..events.events.len()).map(Some).collect();
+    replace_events(contents, events.events, &origins)
+}
+
+/// Replaces the events in the given events file, and formats it in canonical form like
+/// `format_events_file`. `origins[i]` is the index of the existing event in the file which
+/// `events[i]` replaces, if any, so that its comments can be kept. Comments of existing events which
+/// aren't replaced are dropped.
+pub fn replace_events(
+    contents: &str,
+    events: Vec<Event>,
+    origins: &[Option<usize>],
+) -> Result<String, Report> {
+    let item_lines: Vec<usize> = event_positions(contents)
+        .iter()
+        .map(|marker| marker.line() - 1)
+        .collect();
+    let mut comments = FileComments::parse(contents, &item_lines);
+
+    let mut commented_events: Vec<_> = events
+        .into_iter()
+        .zip(origins.iter().chain(std::iter::repeat(&None)))
+        .map(|(event, origin)| {
+            let event_comments = origin
+                .and_then(|origin| comments.event_comments.get_mut(origin))
+                .map(std::mem::take)
+                .unwrap_or_default();
+            (event, event_comments)
+        })
+        .collect();
+    commented_events.sort_by_key(|(event, _)| event.start_time_sort_key());
+    let (sorted_events, sorted_comments): (Vec<_>, Vec<_>) = commented_events.into_iter().unzip();
+
+    let yaml = serde_yaml::to_string(&Events {
+        events: sorted_events,
+    })?;
+    let mut output = String::new();
+    output += comments
+        .schema_header
+        .as_deref()
+        .unwrap_or(DEFAULT_SCHEMA_HEADER);
+    output.push('\n');
+    for comment in &comments.header {
+        output += comment;
+        output.push('\n');
+    }
+    let mut sorted_comments = sorted_comments.into_iter();
+    for line in yaml.lines().skip_while(|line| *line == "---") {
+        if line.starts_with("  - ") {
+            for comment in sorted_comments.next().unwrap_or_default() {
+                output += "  ";
+                output += &comment;
+                output.push('\n');
+            }
+        }
+        if let Some(block) = block_scalar(line) {
+            output += &block;
+        } else {
+            output += line;
+            output.push('\n');
+        }
+    }
+    for comment in &comments.footer {
+        output += comment;
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+/// The comments in an events file, grouped by what they apply to.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct FileComments {
+    /// The existing schema header, if any.
+    schema_header: Option<String>,
+    /// Comments before the start of the events list.
+    header: Vec<String>,
+    /// For each event in order, the comments before and within it.
+    event_comments: Vec<Vec<String>>,
+    /// Comments after the last event.
+    footer: Vec<String>,
+}
+
+impl FileComments {
+    /// Finds the comments in the given file, where `item_lines` are the line numbers (counting from
+    /// 0) on which each event starts.
+    fn parse(contents: &str, item_lines: &[usize]) -> Self {
+        let mut comments = Self::default();
+        let mut pending = vec![];
+        // The indentation of the line which started the block scalar currently being read, if any.
+        let mut block_indent = None;
+        for (line_number, line) in contents.lines().enumerate() {
+            let trimmed = line.trim();
+            let indent = line.len() - line.trim_start().len();
+            if let Some(block_indent) = block_indent {
+                if trimmed.is_empty() || indent > block_indent {
+                    continue;
+                }
+            }
+            block_indent = None;
+
+            if trimmed.starts_with(SCHEMA_HEADER_PREFIX) && comments.schema_header.is_none() {
+                comments.schema_header = Some(trimmed.to_owned());
+            } else if trimmed.starts_with('#') {
+                pending.push(trimmed.to_owned());
+            } else if !trimmed.is_empty() && trimmed != "---" {
+                if item_lines.contains(&line_number) {
+                    comments.event_comments.push(vec![]);
+                }
+                let content = if let Some(comment) = end_of_line_comment(trimmed) {
+                    pending.push(comment.to_owned());
+                    trimmed[..trimmed.len() - comment.len()].trim_end()
+                } else {
+                    trimmed
+                };
+                if starts_block_scalar(content) {
+                    block_indent = Some(indent);
+                }
+                if let Some(event_comments) = comments.event_comments.last_mut() {
+                    event_comments.append(&mut pending);
+                } else {
+                    comments.header.append(&mut pending);
+                }
+            }
+        }
+        comments.footer = pending;
+        comments
+    }
+}
+
+/// Rewrites a line of YAML mapping a key to a quoted multi-line string as a literal block scalar,
+/// which is easier to read and edit. Returns `None` if the line isn't a multi-line string, or the
+/// string can't be written as a block scalar without changing it.
+fn block_scalar(line: &str) -> Option<String> {
+    let (key, quoted) = line.split_once(": \"")?;
+    let value: String = serde_yaml::from_str(&format!("\"{}", quoted)).ok()?;
+    let (contents, chomping) = match value.strip_suffix('\n') {
+        Some(contents) => (contents, ""),
+        None => (value.as_str(), "-"),
+    };
+    // Trailing whitespace would be invisible, and is often removed by editors.
+    if !contents.contains('\n')
+        || contents.starts_with([' ', '\n'])
+        || contents
+            .lines()
+            .any(|line| line.ends_with(char::is_whitespace))
+    {
+        return None;
+    }
+    // The contents are indented relative to the key, which may come after the "- " of a list item.
+    let indent = key.len() - key.trim_start_matches([' ', '-']).len() + 2;
+    let mut block = format!("{}: |{}\n", key, chomping);
+    let mut check = format!("value: |{}\n", chomping);
+    for content_line in contents.lines() {
+        if !content_line.is_empty() {
+            block += &" ".repeat(indent);
+            block += content_line;
+            check += "  ";
+            check += content_line;
+        }
+        block.push('\n');
+        check.push('\n');
+    }
+    // Check that the block scalar has the same value, in case of anything else it can't represent.
+    let parsed: serde_yaml::Mapping = serde_yaml::from_str(&check).ok()?;
+    if parsed.iter().next()?.1.as_str()? == value {
+        Some(block)
+    } else {
+        None
+    }
+}
+
+/// Returns whether the given line of YAML ends with the start of a literal or folded block scalar,
+/// such that the following more indented lines are its contents.
+fn starts_block_scalar(line: &str) -> bool {
+    let indicator = line
+        .trim_end_matches(|c: char| c.is_ascii_digit() || c == '+' || c == '-')
+        .trim_end();
+    (indicator.ends_with(": |") || indicator.ends_with(": >") || indicator == "- |")
+        || indicator == "- >"
+}
+
+/// Finds a comment at the end of the given line of YAML, if any.
+fn end_of_line_comment(line: &str) -> Option<&str> {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut previous = ' ';
+    // The last character outside quotes which wasn't whitespace, if any.
+    let mut last_indicator = None;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            // A quote only starts a quoted scalar at the start of a value, not e.g. in "St Peter's".
+            None if (c == '"' || c == '\'')
+                && (previous.is_whitespace() || matches!(previous, '[' | '{' | ','))
+                && matches!(
+                    last_indicator,
+                    None | Some(':' | '-' | '[' | '{' | ',' | '?')
+                ) =>
+            {
+                quote = Some(c)
+            }
+            None if c == '#' && previous.is_whitespace() => return Some(&line[i..]),
+            None => {}
+        }
+        if quote.is_none() && !c.is_whitespace() {
+            last_indicator = Some(c);
+        }
+        previous = c;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts_and_keeps_comments() {
+        let contents = r#"# Events in Gent.
+events:
+  # The second event.
+  - name: "Second"
+    city: Gent
+    country: Belgium
+    start_date: 2022-02-01
+    end_date: 2022-02-01
+    styles: [balfolk] # Maybe contra too.
+    social: true
+  - name: First
+    country: Belgium
+    city: Gent
+    start_date: 2022-01-01
+    end_date: 2022-01-01
+    styles: [balfolk]
+    workshop: true
+    details: |
+      # Not a comment.
+      More details.
+# The end.
+"#;
+        let formatted = format_events_file(contents).unwrap();
+        assert_eq!(
+            formatted,
+            r##"# yaml-language-server: $schema=../events_schema.json
+# Events in Gent.
+events:
+  - name: First
+    details: |
+      # Not a comment.
+      More details.
+    links: []
+    start_date: 2022-01-01
+    end_date: 2022-01-01
+    country: Belgium
+    city: Gent
+    styles:
+      - balfolk
+    workshop: true
+    social: false
+    organisation: ~
+  # The second event.
+  # Maybe contra too.
+  - name: Second
+    links: []
+    start_date: 2022-02-01
+    end_date: 2022-02-01
+    country: Belgium
+    city: Gent
+    styles:
+      - balfolk
+    workshop: false
+    social: true
+    organisation: ~
+# The end.
+"##
+        );
+        assert_eq!(format_events_file(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn hash_in_string() {
+        assert_eq!(end_of_line_comment("name: 'Dance #1'"), None);
+        assert_eq!(
+            end_of_line_comment("name: Dance # Comment"),
+            Some("# Comment")
+        );
+        assert_eq!(
+            end_of_line_comment("name: St Peter's Hall # note"),
+            Some("# note")
+        );
+        assert_eq!(
+            end_of_line_comment(r#"name: "Say \"hi\" # here" # there"#),
+            Some("# there")
+        );
+        assert_eq!(
+            end_of_line_comment("bands: [Duo, 'A # B'] # Two bands."),
+            Some("# Two bands.")
+        );
+    }
+
+    #[test]
+    fn multi_line_strings_as_block_scalars() {
+        assert_eq!(
+            block_scalar(r#"    details: "First line\n\nSecond line\n""#).unwrap(),
+            "    details: |\n      First line\n\n      Second line\n"
+        );
+        assert_eq!(
+            block_scalar(r#"    details: "First\nSecond""#).unwrap(),
+            "    details: |-\n      First\n      Second\n"
+        );
+        assert_eq!(
+            block_scalar(r#"  - name: "Two\nlines""#).unwrap(),
+            "  - name: |-\n      Two\n      lines\n"
+        );
+        assert_eq!(block_scalar(r#"    details: "One line""#), None);
+        assert_eq!(block_scalar(r#"    details: "Trailing \nspace""#), None);
+        assert_eq!(block_scalar(r#"    details: " Indented\nline""#), None);
+    }
+}
@@ -1,20 +1,174 @@
-use crate::model::event::{Event, EventTime};
+use crate::model::event::{Event, EventTime, Venue};
 use axum::{
     body::{boxed, Full},
     http::{header, HeaderValue},
     response::{IntoResponse, Response},
 };
-use chrono::{Date, Utc};
-use icalendar::{Calendar, Component, EventStatus};
-use std::fmt::Write;
+use chrono::{Date, DateTime, Datelike, Duration, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+use icalendar::{Calendar, Component, EventStatus, Property};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{Mutex, OnceLock},
+};
+
+/// The format used for local date-times in iCalendar, without a timezone suffix.
+const LOCAL_DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// The offset transitions of each timezone in each year for which they have been needed.
+static TRANSITIONS: OnceLock<Mutex<TransitionCache>> = OnceLock::new();
 
-pub fn events_to_calendar(events: &[&Event]) -> Calendar {
-    events
+type TransitionCache = HashMap<(Tz, i32), Vec<DateTime<Utc>>>;
+
+/// Converts the given events to an iCalendar file, including VTIMEZONE components for any named
+/// timezones which they use.
+pub fn events_to_calendar(events: &[&Event]) -> String {
+    let calendar = events
         .iter()
         .map(|event| event_to_event(event))
         .collect::<Calendar>()
         .name("Folk dance events")
         .done()
+        .to_string();
+
+    let mut timezones = String::new();
+    for (timezone, start_year, end_year) in timezone_years(events) {
+        write_vtimezone(&mut timezones, timezone, start_year, end_year).unwrap();
+    }
+
+    // The icalendar crate doesn't support VTIMEZONE components, so insert them before the first
+    // component.
+    let position = calendar
+        .find("BEGIN:VEVENT")
+        .or_else(|| calendar.find("END:VCALENDAR"))
+        .unwrap();
+    let mut calendar = calendar;
+    calendar.insert_str(position, &timezones);
+    calendar
+}
+
+/// Finds all named timezones used by the given events, along with the range of years for which
+/// they are needed.
+fn timezone_years(events: &[&Event]) -> Vec<(Tz, i32, i32)> {
+    let mut timezones: Vec<(Tz, i32, i32)> = vec![];
+    for event in events {
+        if let (Some(timezone), EventTime::DateTime { start, end }) = (event.timezone, &event.time)
+        {
+            if let Some(entry) = timezones.iter_mut().find(|(tz, _, _)| *tz == timezone) {
+                entry.1 = entry.1.min(start.year());
+                entry.2 = entry.2.max(end.year());
+            } else {
+                timezones.push((timezone, start.year(), end.year()));
+            }
+        }
+    }
+    timezones.sort_by_key(|(timezone, _, _)| timezone.name());
+    timezones
+}
+
+/// Writes a VTIMEZONE component for the given timezone, covering all the offset transitions
+/// between the start of `start_year` and the end of `end_year`.
+fn write_vtimezone(
+    out: &mut String,
+    timezone: Tz,
+    start_year: i32,
+    end_year: i32,
+) -> Result<(), std::fmt::Error> {
+    let range_start = Utc.ymd(start_year, 1, 1).and_hms(0, 0, 0);
+
+    write!(out, "BEGIN:VTIMEZONE\r\nTZID:{}\r\n", timezone.name())?;
+    let initial = range_start.with_timezone(&timezone);
+    write_observance(out, initial, initial)?;
+    let mut previous = initial;
+    for year in start_year..=end_year {
+        for transition in transitions(timezone, year) {
+            let transition = transition.with_timezone(&timezone);
+            write_observance(out, previous, transition)?;
+            previous = transition;
+        }
+    }
+    write!(out, "END:VTIMEZONE\r\n")
+}
+
+/// Gets the instants during the given year at which the offset of the timezone changes. These are
+/// cached, as finding them means checking every day of the year.
+fn transitions(timezone: Tz, year: i32) -> Vec<DateTime<Utc>> {
+    let cache = TRANSITIONS.get_or_init(Default::default);
+    if let Some(transitions) = cache.lock().unwrap().get(&(timezone, year)) {
+        return transitions.clone();
+    }
+
+    let mut transitions = vec![];
+    let mut day = Utc.ymd(year, 1, 1).and_hms(0, 0, 0);
+    let year_end = Utc.ymd(year + 1, 1, 1).and_hms(0, 0, 0);
+    while day < year_end {
+        let next_day = day + Duration::days(1);
+        if next_day.with_timezone(&timezone).offset() != day.with_timezone(&timezone).offset() {
+            transitions.push(find_transition(timezone, day, next_day).with_timezone(&Utc));
+        }
+        day = next_day;
+    }
+    cache
+        .lock()
+        .unwrap()
+        .insert((timezone, year), transitions.clone());
+    transitions
+}
+
+/// Finds the first instant between `start` and `end` at which the offset of the timezone differs
+/// from its offset at `start`.
+fn find_transition(timezone: Tz, mut start: DateTime<Utc>, mut end: DateTime<Utc>) -> DateTime<Tz> {
+    let offset = start.with_timezone(&timezone).offset().to_owned();
+    while end - start > Duration::seconds(1) {
+        let middle = start + (end - start) / 2;
+        if middle.with_timezone(&timezone).offset() == &offset {
+            start = middle;
+        } else {
+            end = middle;
+        }
+    }
+    end.with_timezone(&timezone)
+}
+
+/// Writes a STANDARD or DAYLIGHT sub-component for a transition from the offset at `before` to the
+/// offset at `after`.
+fn write_observance(
+    out: &mut String,
+    before: DateTime<Tz>,
+    after: DateTime<Tz>,
+) -> Result<(), std::fmt::Error> {
+    let kind = if after.offset().dst_offset().is_zero() {
+        "STANDARD"
+    } else {
+        "DAYLIGHT"
+    };
+    // DTSTART of an observance is given in the local time before the transition.
+    let local_start = after.with_timezone(&before.offset().fix()).naive_local();
+    write!(out, "BEGIN:{}\r\n", kind)?;
+    write!(
+        out,
+        "DTSTART:{}\r\n",
+        local_start.format(LOCAL_DATE_TIME_FORMAT)
+    )?;
+    write!(out, "TZOFFSETFROM:{}\r\n", format_offset(before))?;
+    write!(out, "TZOFFSETTO:{}\r\n", format_offset(after))?;
+    write!(out, "TZNAME:{}\r\n", after.offset().abbreviation())?;
+    write!(out, "END:{}\r\n", kind)
+}
+
+/// Formats the UTC offset of the given time in the form used by iCalendar, e.g. `+0130`.
+fn format_offset(time: DateTime<Tz>) -> String {
+    let minutes = time.offset().fix().local_minus_utc() / 60;
+    let sign = if minutes < 0 { '-' } else { '+' };
+    format!("{}{:02}{:02}", sign, minutes.abs() / 60, minutes.abs() % 60)
+}
+
+/// Makes a DTSTART or DTEND property for the given local time in the given timezone.
+fn local_time_property(key: &str, time: NaiveDateTime, timezone: Tz) -> Property {
+    Property::new(key, &time.format(LOCAL_DATE_TIME_FORMAT).to_string())
+        .add_parameter("TZID", timezone.name())
+        .done()
 }
 
 fn event_to_event(event: &Event) -> icalendar::Event {
@@ -50,12 +204,18 @@ fn event_to_event(event: &Event) -> icalendar::Event {
     if !event.callers.is_empty() {
         writeln!(description, "Callers: {}", event.callers.join(", ")).unwrap();
     }
+    if !event.teachers.is_empty() {
+        writeln!(description, "Teachers: {}", event.teachers.join(", ")).unwrap();
+    }
     if let Some(price) = &event.price {
-        writeln!(description, "Price: {}", price).unwrap();
+        writeln!(description, "Price: {}", price.long_description()).unwrap();
     }
     for link in &event.links {
         writeln!(description, "{}", link).unwrap();
     }
+    if let Some(source) = &event.source {
+        writeln!(description, "Source: {}", source.credit()).unwrap();
+    }
 
     let categories = event
         .styles
@@ -66,15 +226,21 @@ fn event_to_event(event: &Event) -> icalendar::Event {
 
     let mut calendar_event = icalendar::Event::new();
     calendar_event
+        .uid(&format!("{}@dancelist", event.id()))
         .summary(&event.name)
-        .location(&format!("{}, {}", event.city, event.country))
+        .location(&event.location())
         .description(&description)
         .status(if event.cancelled {
             EventStatus::Cancelled
+        } else if event.tentative {
+            EventStatus::Tentative
         } else {
             EventStatus::Confirmed
         })
         .add_property("CATEGORIES", &categories);
+    if let Some((latitude, longitude)) = event.venue.as_ref().and_then(Venue::coordinates) {
+        calendar_event.add_property("GEO", &format!("{};{}", latitude, longitude));
+    }
     match event.time {
         EventTime::DateOnly {
             start_date,
@@ -86,9 +252,23 @@ fn event_to_event(event: &Event) -> icalendar::Event {
                 .end_date(Date::<Utc>::from_utc(end_date.succ(), Utc));
         }
         EventTime::DateTime { start, end } => {
-            calendar_event
-                .starts(start.with_timezone(&Utc))
-                .ends(end.with_timezone(&Utc));
+            if let Some(timezone) = event.timezone {
+                calendar_event
+                    .append_property(local_time_property(
+                        "DTSTART",
+                        start.with_timezone(&timezone).naive_local(),
+                        timezone,
+                    ))
+                    .append_property(local_time_property(
+                        "DTEND",
+                        end.with_timezone(&timezone).naive_local(),
+                        timezone,
+                    ));
+            } else {
+                calendar_event
+                    .starts(start.with_timezone(&Utc))
+                    .ends(end.with_timezone(&Utc));
+            }
         }
     }
     for link in &event.links {
@@ -98,11 +278,11 @@ fn event_to_event(event: &Event) -> icalendar::Event {
 }
 
 #[derive(Debug)]
-pub struct Ics(pub Calendar);
+pub struct Ics(pub String);
 
 impl IntoResponse for Ics {
     fn into_response(self) -> Response {
-        let mut res = Response::new(boxed(Full::from(self.0.to_string())));
+        let mut res = Response::new(boxed(Full::from(self.0)));
         res.headers_mut().insert(
             header::CONTENT_TYPE,
             HeaderValue::from_static("text/calendar"),
@@ -110,3 +290,39 @@ impl IntoResponse for Ics {
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::Brussels;
+
+    #[test]
+    fn vtimezone_transitions() {
+        let mut vtimezone = String::new();
+        write_vtimezone(&mut vtimezone, Brussels, 2022, 2022).unwrap();
+        assert_eq!(
+            vtimezone,
+            "BEGIN:VTIMEZONE\r\n\
+            TZID:Europe/Brussels\r\n\
+            BEGIN:STANDARD\r\n\
+            DTSTART:20220101T010000\r\n\
+            TZOFFSETFROM:+0100\r\n\
+            TZOFFSETTO:+0100\r\n\
+            TZNAME:CET\r\n\
+            END:STANDARD\r\n\
+            BEGIN:DAYLIGHT\r\n\
+            DTSTART:20220327T020000\r\n\
+            TZOFFSETFROM:+0100\r\n\
+            TZOFFSETTO:+0200\r\n\
+            TZNAME:CEST\r\n\
+            END:DAYLIGHT\r\n\
+            BEGIN:STANDARD\r\n\
+            DTSTART:20221030T030000\r\n\
+            TZOFFSETFROM:+0200\r\n\
+            TZOFFSETTO:+0100\r\n\
+            TZNAME:CET\r\n\
+            END:STANDARD\r\n\
+            END:VTIMEZONE\r\n"
+        );
+    }
+}
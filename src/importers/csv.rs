@@ -0,0 +1,100 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{DateWindow, ImportWarning, Imported, Importer, WarningKind};
+use crate::{
+    config::SourceConfig,
+    csv::parse_events,
+    model::{events::Events, source::Source},
+};
+use eyre::{bail, Report};
+
+/// Imports events from a CSV file, such as a spreadsheet sent by an organiser, in the same format
+/// as `/index.csv`.
+pub struct CsvFile;
+
+impl Importer for CsvFile {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    fn description(&self) -> &str {
+        "Events in a CSV file, with the same columns as /index.csv"
+    }
+
+    fn url(&self, _config: &SourceConfig, _window: &DateWindow) -> Result<String, Report> {
+        bail!("CSV files must be given as input, e.g. `import csv events.csv`.");
+    }
+
+    fn convert(&self, raw: &str) -> Result<Imported, Report> {
+        let mut events = vec![];
+        let mut warnings = vec![];
+        for row in parse_events(raw)? {
+            let description = format!("line {} {}", row.line, row.name);
+            match row.event {
+                Ok(mut event) => {
+                    // Events are identified by their ID, which is derived from their date, name
+                    // and city unless the file gives a different one.
+                    event.source = Some(Source {
+                        importer: self.name().to_owned(),
+                        id: event.id(),
+                        fetched: None,
+                        licence: None,
+                        attribution: None,
+                    });
+                    if event.cancelled {
+                        warnings.push(ImportWarning::new(
+                            WarningKind::Cancelled,
+                            &description,
+                            None,
+                        ));
+                    }
+                    events.push(event);
+                }
+                Err(reason) => warnings.push(ImportWarning::new(
+                    WarningKind::Dropped,
+                    &description,
+                    Some(&reason.to_string()),
+                )),
+            }
+        }
+
+        Ok(Imported {
+            events: Events { events },
+            warnings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::event::Event;
+
+    #[test]
+    fn source_ids() {
+        let imported = CsvFile
+            .convert(
+                "name,city,country,start,styles,social\nBal,Gent,Belgium,2030-03-01,balfolk,yes\n",
+            )
+            .unwrap();
+        let event = &imported.events.events[0];
+        assert_eq!(CsvFile.source_id(event).unwrap(), "2030-03-01-bal-gent");
+        let local = Event {
+            source: None,
+            ..event.clone()
+        };
+        assert_eq!(CsvFile.source_id(&local), None);
+    }
+}
@@ -14,86 +14,156 @@
 
 pub mod types;
 
-use self::types::{Event, EventType};
-use crate::model::{
-    dancestyle::DanceStyle,
-    event::{self, EventTime},
-    events::Events,
+use self::types::{Event, EventType, Location, Teacher};
+use super::{DateWindow, ImportWarning, Imported, Importer, WarningKind};
+use crate::{
+    config::SourceConfig,
+    model::{
+        dancestyle::DanceStyle,
+        event::{self, BandDetails, EventTime, Musician, Session, Venue},
+        events::Events,
+        price::{Price, PriceKind, PriceTier},
+        source::Source,
+    },
 };
-use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, Offset, TimeZone};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveTime, Offset, TimeZone};
 use chrono_tz::{Europe::Brussels, Tz};
 use eyre::Report;
 
-pub async fn events() -> Result<Vec<Event>, Report> {
-    let json = reqwest::get("https://folkbalbende.be/interface/events.php?start=2022-02-01&end=3000-01-01&type=ball,course,festal").await?.text().await?;
-    let mut events: Vec<Event> = serde_json::from_str(&json)?;
-    // Sort by ID to give a stable order.
-    events.sort_by_key(|event| event.id);
-    Ok(events)
-}
+/// The name of the importer, which is recorded in the source of each event.
+const NAME: &str = "balbende";
 
-pub async fn import_events() -> Result<Events, Report> {
-    let events = events().await?;
+/// The folkbalbende API endpoint from which to fetch events.
+const DEFAULT_URL: &str = "https://folkbalbende.be/interface/events.php";
+/// The start and end dates to request if the date window doesn't limit them.
+const DEFAULT_START: &str = "2022-02-01";
+const DEFAULT_END: &str = "3000-01-01";
+/// The types of events to request if none are configured.
+const DEFAULT_TYPES: &str = "ball,course,festal";
 
-    // Print warnings about cancelled, deleted and unchecked events.
-    for event in &events {
-        let dates = event
-            .dates
-            .iter()
-            .map(|date| date.to_string())
-            .collect::<Vec<_>>()
-            .join(",");
-        if event.cancelled {
-            eprintln!("Cancelled: {} {}", dates, event.name);
-        }
-        if event.deleted {
-            eprintln!("Deleted: {} {}", dates, event.name);
-        }
-        if !event.checked {
-            eprintln!("Not checked: {} {}", dates, event.name);
+/// The prefix of the link to each event on the folkbalbende website, which is followed by its ID.
+const EVENT_LINK_PREFIX: &str = "https://folkbalbende.be/event/";
+
+/// Imports balfolk events in Belgium from folkbalbende.be.
+pub struct FolkBalBende;
+
+impl Importer for FolkBalBende {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn description(&self) -> &str {
+        "Balfolk events in Belgium from folkbalbende.be"
+    }
+
+    fn url(&self, config: &SourceConfig, window: &DateWindow) -> Result<String, Report> {
+        let types = config
+            .types
+            .as_ref()
+            .map(|types| types.join(","))
+            .unwrap_or_else(|| DEFAULT_TYPES.to_string());
+        Ok(format!(
+            "{}?start={}&end={}&type={}",
+            config.url.as_deref().unwrap_or(DEFAULT_URL),
+            window
+                .start
+                .map_or_else(|| DEFAULT_START.to_string(), |start| start.to_string()),
+            window
+                .end
+                .map_or_else(|| DEFAULT_END.to_string(), |end| end.to_string()),
+            types
+        ))
+    }
+
+    fn convert(&self, raw: &str) -> Result<Imported, Report> {
+        let events = parse_events(raw)?;
+
+        let mut warnings = vec![];
+        for event in &events {
+            let description = format!("{} {}", format_dates(&event.dates), event.name);
+            if event.cancelled {
+                warnings.push(ImportWarning::new(
+                    WarningKind::Cancelled,
+                    &description,
+                    None,
+                ));
+            }
+            if event.deleted {
+                warnings.push(ImportWarning::new(
+                    WarningKind::Dropped,
+                    &description,
+                    Some("deleted"),
+                ));
+            } else if !event.checked {
+                warnings.push(ImportWarning::new(
+                    WarningKind::Unchecked,
+                    &description,
+                    None,
+                ));
+            }
         }
+
+        Ok(Imported {
+            events: Events {
+                events: events
+                    .iter()
+                    .flat_map(|event| {
+                        if event.checked && !event.deleted {
+                            convert(event)
+                        } else {
+                            vec![]
+                        }
+                    })
+                    .collect(),
+            },
+            warnings,
+        })
     }
 
-    Ok(Events {
-        events: events
-            .iter()
-            .flat_map(|event| {
-                if event.checked && !event.deleted {
-                    convert(event)
-                } else {
-                    vec![]
-                }
-            })
-            .collect(),
-    })
+    fn attribution(&self) -> Option<&str> {
+        Some("Event data from folkbalbende.be")
+    }
+
+    fn default_rules(&self) -> &str {
+        include_str!("rules.toml")
+    }
+}
+
+/// Parses the JSON returned by the folkbalbende API.
+fn parse_events(json: &str) -> Result<Vec<Event>, Report> {
+    let mut events: Vec<Event> = serde_json::from_str(json)?;
+    // Sort by ID to give a stable order.
+    events.sort_by_key(|event| event.id);
+    Ok(events)
+}
+
+fn format_dates(dates: &[NaiveDate]) -> String {
+    dates
+        .iter()
+        .map(|date| date.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
 }
 
 fn convert(event: &Event) -> Vec<event::Event> {
-    // Filter out "mailto:" URLs and duplicates in non-English languages.
+    // Filter out "mailto:" URLs.
     let mut links: Vec<String> = event
         .websites
         .iter()
         .filter_map(|website| {
-            if website.url.starts_with("http")
-                && !website
-                    .url
-                    .starts_with("https://frissefolk.be/fr/civicrm/event/info")
-                && !website
-                    .url
-                    .starts_with("https://frissefolk.be/nl/civicrm/event/info")
-            {
+            if website.url.starts_with("http") {
                 Some(website.url.to_owned())
             } else {
                 None
             }
         })
         .collect();
-    links.push(format!("https://folkbalbende.be/event/{}", event.id));
+    links.push(format!("{}{}", EVENT_LINK_PREFIX, event.id));
     if !event.facebook_event.is_empty() {
         links.push(event.facebook_event.to_owned());
     }
 
-    let details = format!("{:?}", event.event_type);
+    let details = description(&event.en, &event.nl, &event.fr);
 
     let mut workshop = event.event_type == EventType::Course || !event.courses.is_empty();
     if let Some(ball) = &event.ball {
@@ -107,32 +177,7 @@ fn convert(event: &Event) -> Vec<event::Event> {
         EventType::Ball | EventType::Festival => true,
     };
 
-    let price = if event.prices.is_empty() {
-        None
-    } else {
-        let prices: Vec<_> = event
-            .prices
-            .iter()
-            .filter_map(|price| {
-                if price.price == 0 {
-                    None
-                } else {
-                    Some(price.price)
-                }
-            })
-            .collect();
-        let min_price = prices.iter().min();
-        let max_price = prices.iter().max();
-        if let (Some(min_price), Some(max_price)) = (min_price, max_price) {
-            Some(if min_price == max_price {
-                format!("€{}", min_price)
-            } else {
-                format!("€{}-€{}", min_price, max_price)
-            })
-        } else {
-            None
-        }
-    };
+    let price = convert_prices(&event.prices);
 
     let bands = if let Some(ball) = &event.ball {
         ball.performances
@@ -149,11 +194,19 @@ fn convert(event: &Event) -> Vec<event::Event> {
         vec![]
     };
 
-    let organisation = if let Some(organisation) = &event.organisation {
-        Some(organisation.name.to_owned())
-    } else {
-        None
-    };
+    let band_details = convert_band_details(event);
+
+    let mut teachers = teacher_names(event.courses.iter().flat_map(|course| &course.teachers));
+    if let Some(ball) = &event.ball {
+        teachers.extend(teacher_names(&ball.initiators));
+    }
+    teachers.sort();
+    teachers.dedup();
+
+    let organisation = event
+        .organisation
+        .as_ref()
+        .map(|organisation| organisation.name.to_owned());
 
     // Find the earliest start time and latest finish time, if any.
     let mut start_times: Vec<NaiveTime> = event.courses.iter().map(|course| course.start).collect();
@@ -161,16 +214,16 @@ fn convert(event: &Event) -> Vec<event::Event> {
     if let Some(ball) = &event.ball {
         start_times.extend(ball.initiation_start);
         end_times.extend(ball.initiation_end);
+        let performances = ball
+            .performances
+            .iter()
+            .filter(|performance| !performance.band.placeholder);
         start_times.extend(
-            ball.performances
-                .iter()
+            performances
+                .clone()
                 .flat_map(|performance| performance.start),
         );
-        end_times.extend(
-            ball.performances
-                .iter()
-                .flat_map(|performance| performance.end),
-        );
+        end_times.extend(performances.flat_map(|performance| performance.end));
     }
     let mut start_time = start_times.into_iter().min();
     if start_time == Some(NaiveTime::from_hms(0, 0, 0)) {
@@ -178,56 +231,428 @@ fn convert(event: &Event) -> Vec<event::Event> {
     }
     let end_time = end_times.into_iter().max();
 
-    event
-        .dates
-        .iter()
-        .map(|&date| event::Event {
-            name: event.name.clone(),
-            details: Some(details.clone()),
-            links: links.clone(),
-            time: make_time(date, start_time, end_time),
-            country: "Belgium".to_string(),
-            city: event.location.address.city.clone(),
-            styles: vec![DanceStyle::Balfolk],
-            workshop,
-            social,
-            bands: bands.clone(),
-            callers: vec![],
-            price: price.clone(),
-            organisation: organisation.clone(),
-            cancelled: event.cancelled,
+    let venue = convert_location(&event.location);
+
+    consecutive_runs(&event.dates)
+        .into_iter()
+        .enumerate()
+        .map(|(run, (start_date, end_date))| {
+            // folkbalbende doesn't say which day of a multi-day run each session is on, so they are
+            // all put on the first day.
+            let schedule = make_schedule(event, start_date);
+            let mut details = details.clone();
+            if start_date != end_date && !schedule.is_empty() {
+                let note =
+                    "The schedule gives the times of the sessions, but not which day of the \
+                    event each is on.";
+                details = Some(match details {
+                    Some(details) => format!("{}\n\n{}", details, note),
+                    None => note.to_owned(),
+                });
+            }
+            let mut time = make_time(start_date, end_date, start_time, end_time);
+            // Sessions may run past midnight, so take the start and end of the event from them,
+            // with the latest session on the last day.
+            if let (EventTime::DateTime { start, end }, Some(first), Some(last)) = (
+                &mut time,
+                schedule.iter().map(|session| session.start).min(),
+                schedule
+                    .iter()
+                    .map(|session| session.end)
+                    .max()
+                    .and_then(|last| shift_days(last, end_date - start_date)),
+            ) {
+                *start = first;
+                *end = last;
+            }
+            event::Event {
+                id: None,
+                name: event.name.clone(),
+                details,
+                links: links.clone(),
+                time,
+                timezone: Some(Brussels),
+                // folkbalbende doesn't give the country, so it is set by the rules.
+                country: String::new(),
+                city: event.location.address.city.clone(),
+                region: None,
+                styles: vec![DanceStyle::Balfolk],
+                workshop,
+                social,
+                bands: bands.clone(),
+                callers: vec![],
+                teachers: teachers.clone(),
+                price: price.clone(),
+                organisation: organisation.clone(),
+                cancelled: event.cancelled,
+                tentative: false,
+                venue: venue.clone(),
+                schedule,
+                band_details: band_details.clone(),
+                recurrence: None,
+                // The runs of an event are identified by their order, so that they still match if
+                // the event is moved to different dates.
+                source: Some(Source {
+                    importer: NAME.to_owned(),
+                    id: format!("{}-{}", event.id, run + 1),
+                    fetched: None,
+                    licence: None,
+                    attribution: None,
+                }),
+            }
         })
         .collect()
 }
 
+/// Picks the description in English if there is one, or otherwise in Dutch or French.
+fn description(en: &str, nl: &str, fr: &str) -> Option<String> {
+    [en, nl, fr]
+        .into_iter()
+        .map(str::trim)
+        .find(|description| !description.is_empty())
+        .map(ToOwned::to_owned)
+}
+
+fn teacher_names<'a>(teachers: impl IntoIterator<Item = &'a Teacher>) -> Vec<String> {
+    teachers
+        .into_iter()
+        .map(|teacher| teacher.name.to_owned())
+        .collect()
+}
+
+/// Converts the details of the bands playing at the event, skipping placeholders.
+fn convert_band_details(event: &Event) -> Vec<BandDetails> {
+    let non_empty = |value: &str| {
+        if value.is_empty() {
+            None
+        } else {
+            Some(value.to_owned())
+        }
+    };
+    let mut band_details: Vec<BandDetails> = vec![];
+    for performance in event.ball.iter().flat_map(|ball| &ball.performances) {
+        let band = &performance.band;
+        if band.placeholder || band_details.iter().any(|details| details.name == band.name) {
+            continue;
+        }
+        band_details.push(BandDetails {
+            name: band.name.to_owned(),
+            country: non_empty(&band.country.name),
+            musicians: band
+                .musicians
+                .iter()
+                .map(|musician| Musician {
+                    name: musician.name.to_owned(),
+                    instruments: non_empty(&musician.instruments),
+                    country: non_empty(&musician.country.name),
+                })
+                .collect(),
+        });
+    }
+    band_details
+}
+
+/// Makes the schedule of initiation, courses and performances for the event on the given date.
+/// Sessions without a known time and performances by placeholder bands are left out.
+fn make_schedule(event: &Event, date: NaiveDate) -> Vec<Session> {
+    let mut schedule = vec![];
+    let mut add_session = |name: &str, start: NaiveTime, end: NaiveTime, teachers: Vec<String>| {
+        if start == end {
+            return;
+        }
+        // Sessions which end at or after midnight finish the next day.
+        let end_date = if end < start { date.succ() } else { date };
+        if let (Some(start), Some(end)) = (local_time(date, start), local_time(end_date, end)) {
+            schedule.push(Session {
+                name: name.to_owned(),
+                start,
+                end,
+                teachers,
+            });
+        }
+    };
+
+    for course in &event.courses {
+        add_session(
+            &course.title,
+            course.start,
+            course.end,
+            teacher_names(&course.teachers),
+        );
+    }
+    if let Some(ball) = &event.ball {
+        if let (Some(start), Some(end)) = (ball.initiation_start, ball.initiation_end) {
+            add_session("Initiation", start, end, teacher_names(&ball.initiators));
+        }
+        for performance in &ball.performances {
+            if performance.band.placeholder {
+                continue;
+            }
+            if let (Some(start), Some(end)) = (performance.start, performance.end) {
+                add_session(&performance.band.name, start, end, vec![]);
+            }
+        }
+    }
+
+    schedule.sort_by_key(|session| session.start);
+    schedule
+}
+
+fn convert_prices(prices: &[types::Price]) -> Option<Price> {
+    if prices.is_empty() {
+        return None;
+    }
+
+    let kind = if prices.iter().any(|price| price.free_contribution != 0) {
+        PriceKind::Donation
+    } else if prices.iter().all(|price| price.price == 0) {
+        PriceKind::Free
+    } else {
+        PriceKind::Fixed
+    };
+    // A zero price is a free tier, unless it is only there to say that donations are welcome.
+    let tiers: Vec<_> = prices
+        .iter()
+        .filter(|price| price.price != 0 || price.free_contribution == 0)
+        .collect();
+    let (min, tiers) = match tiers.as_slice() {
+        _ if kind == PriceKind::Free => (None, vec![]),
+        [price] => (Some(price.price.into()), vec![]),
+        tiers => (
+            None,
+            tiers
+                .iter()
+                .map(|price| PriceTier {
+                    name: price.name.to_owned(),
+                    amount: price.price.into(),
+                })
+                .collect(),
+        ),
+    };
+
+    Some(Price {
+        kind,
+        currency: Some("EUR".to_string()),
+        min,
+        max: None,
+        tiers,
+        description: None,
+    })
+}
+
+fn convert_location(location: &Location) -> Option<Venue> {
+    let address = &location.address;
+    let name = if location.name.is_empty() {
+        None
+    } else {
+        Some(location.name.to_owned())
+    };
+    let street = match (&address.street, &address.number) {
+        (Some(street), Some(number)) if !number.is_empty() => {
+            Some(format!("{} {}", street, number))
+        }
+        (Some(street), _) if !street.is_empty() => Some(street.to_owned()),
+        _ => None,
+    };
+    let postcode = if address.zip.is_empty() {
+        None
+    } else {
+        Some(address.zip.to_owned())
+    };
+    // folkbalbende uses 0, 0 for addresses which haven't been geocoded.
+    let (latitude, longitude) = if address.lat == 0.0 && address.lng == 0.0 {
+        (None, None)
+    } else {
+        (Some(address.lat), Some(address.lng))
+    };
+
+    if name.is_none() && street.is_none() {
+        None
+    } else {
+        Some(Venue {
+            name,
+            street,
+            postcode,
+            city: None,
+            country: None,
+            latitude,
+            longitude,
+        })
+    }
+}
+
+/// Groups the given dates into runs of consecutive days, returning the first and last date of each
+/// run in order.
+fn consecutive_runs(dates: &[NaiveDate]) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut dates = dates.to_vec();
+    dates.sort();
+    dates.dedup();
+    let mut runs: Vec<(NaiveDate, NaiveDate)> = vec![];
+    for date in dates {
+        match runs.last_mut() {
+            Some((_, end)) if end.succ() == date => *end = date,
+            _ => runs.push((date, date)),
+        }
+    }
+    runs
+}
+
+/// Makes the time of an event lasting from `start_date` to `end_date`, starting at `start_time` on
+/// the first day and finishing at `end_time` on the last day if both are known.
 fn make_time(
-    date: NaiveDate,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
     start_time: Option<NaiveTime>,
     end_time: Option<NaiveTime>,
 ) -> EventTime {
     if let (Some(start_time), Some(end_time)) = (start_time, end_time) {
         if let (Some(start), Some(end)) = (
-            Brussels
-                .from_local_datetime(&date.and_time(start_time))
-                .single(),
-            Brussels
-                .from_local_datetime(&date.and_time(end_time))
-                .single(),
+            local_time(start_date, start_time),
+            local_time(end_date, end_time),
         ) {
-            return EventTime::DateTime {
-                start: to_fixed_offset(start),
-                end: to_fixed_offset(end),
-            };
+            return EventTime::DateTime { start, end };
         }
     }
 
     EventTime::DateOnly {
-        start_date: date,
-        end_date: date,
+        start_date,
+        end_date,
     }
 }
 
+/// Moves the given time by the given number of days, keeping the same local time of day in Belgium.
+fn shift_days(time: DateTime<FixedOffset>, days: Duration) -> Option<DateTime<FixedOffset>> {
+    let local = time.with_timezone(&Brussels).naive_local() + days;
+    local_time(local.date(), local.time())
+}
+
+/// Converts the given local date and time in Belgium to a fixed offset time, if it is unambiguous.
+fn local_time(date: NaiveDate, time: NaiveTime) -> Option<DateTime<FixedOffset>> {
+    let date_time = Brussels
+        .from_local_datetime(&date.and_time(time))
+        .single()?;
+    Some(to_fixed_offset(date_time))
+}
+
 fn to_fixed_offset(date_time: DateTime<Tz>) -> DateTime<FixedOffset> {
     let fixed_offset = date_time.offset().fix();
     date_time.with_timezone(&fixed_offset)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{format::events_yaml, importers::rules::Rules};
+
+    #[test]
+    fn url_for_window() {
+        let config = SourceConfig {
+            url: Some("http://mirror.example/events.php".to_string()),
+            types: Some(vec!["ball".to_string(), "festal".to_string()]),
+            ..Default::default()
+        };
+        let window = DateWindow {
+            start: Some(NaiveDate::from_ymd(2022, 5, 1)),
+            end: None,
+        };
+        assert_eq!(
+            FolkBalBende.url(&config, &window).unwrap(),
+            "http://mirror.example/events.php?start=2022-05-01&end=3000-01-01&type=ball,festal"
+        );
+        assert_eq!(
+            FolkBalBende
+                .url(&SourceConfig::default(), &DateWindow::default())
+                .unwrap(),
+            "https://folkbalbende.be/interface/events.php?start=2022-02-01&end=3000-01-01&type=ball,course,festal"
+        );
+    }
+
+    #[test]
+    fn convert_fixture() {
+        let mut imported = FolkBalBende
+            .convert(include_str!("testdata/events.json"))
+            .unwrap();
+        Rules::parse(FolkBalBende.default_rules())
+            .unwrap()
+            .apply(&mut imported);
+        assert_eq!(
+            events_yaml(&imported.events).unwrap(),
+            include_str!("testdata/events.yaml")
+        );
+        assert_eq!(
+            imported
+                .warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "Dropped: 2030-02-01 Verwijderd bal (deleted)",
+                "Cancelled: 2030-07-05,2030-07-06,2030-07-07,2030-07-12 Folkfestival Brugge",
+                "Not checked: 2030-04-01 Nog niet nagekeken bal",
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_rules_without_country() {
+        let mut imported = FolkBalBende
+            .convert(include_str!("testdata/events.json"))
+            .unwrap();
+        Rules::default().apply(&mut imported);
+        imported.drop_invalid();
+        assert_eq!(imported.events.events, vec![]);
+        assert!(imported.warnings.iter().any(|warning| {
+            warning.to_string()
+                == "Dropped: 2030-03-08 Bal in de Vooruit (Country must not be empty.)"
+        }));
+    }
+
+    #[test]
+    fn group_consecutive_dates() {
+        let date = |day| NaiveDate::from_ymd(2030, 7, day);
+        assert_eq!(
+            consecutive_runs(&[date(12), date(5), date(6), date(7), date(6), date(14)]),
+            vec![
+                (date(5), date(7)),
+                (date(12), date(12)),
+                (date(14), date(14)),
+            ]
+        );
+        assert_eq!(consecutive_runs(&[]), vec![]);
+    }
+
+    #[test]
+    fn free_tier_kept() {
+        let price = |name: &str, price, free_contribution| types::Price {
+            name: name.to_string(),
+            price,
+            free_contribution,
+        };
+        assert_eq!(
+            convert_prices(&[price("Inkom", 10, 0), price("Kinderen", 0, 0)]),
+            Some(Price {
+                currency: Some("EUR".to_string()),
+                tiers: vec![
+                    PriceTier {
+                        name: "Inkom".to_string(),
+                        amount: 10.0,
+                    },
+                    PriceTier {
+                        name: "Kinderen".to_string(),
+                        amount: 0.0,
+                    },
+                ],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            convert_prices(&[price("Vrije bijdrage", 0, 1), price("Inkom", 5, 0)]),
+            Some(Price {
+                kind: PriceKind::Donation,
+                currency: Some("EUR".to_string()),
+                min: Some(5.0),
+                ..Default::default()
+            })
+        );
+    }
+}
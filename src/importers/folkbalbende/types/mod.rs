@@ -78,8 +78,8 @@ pub struct Address {
     pub zip_city: String,
     pub city: String,
     pub zip: String,
-    pub lat: f32,
-    pub lng: f32,
+    pub lat: f64,
+    pub lng: f64,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
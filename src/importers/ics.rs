@@ -0,0 +1,490 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{location::parse_location, DateWindow, ImportWarning, Imported, Importer, WarningKind};
+use crate::{
+    config::{EventDefaults, SourceConfig},
+    model::{
+        event::{Event, EventTime, Venue},
+        events::Events,
+        source::Source,
+    },
+};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use eyre::{bail, eyre, Report};
+use icalendar::parser::{read_calendar, unfold, Component, Property};
+
+/// The name of the importer for iCalendar files which aren't configured as a named source.
+pub const GENERIC_NAME: &str = "ics";
+
+/// Imports events from an iCalendar feed. As iCalendar has no fields for dance styles and so on,
+/// these are taken from the configured defaults for the feed.
+pub struct Ics {
+    name: String,
+    description: String,
+    defaults: EventDefaults,
+}
+
+impl Ics {
+    pub fn new(name: &str, config: &SourceConfig) -> Self {
+        let description = if let Some(url) = &config.url {
+            format!("iCalendar feed from {}", url)
+        } else {
+            "iCalendar file, with defaults from the config file".to_string()
+        };
+        Self {
+            name: name.to_owned(),
+            description,
+            defaults: config.defaults.clone(),
+        }
+    }
+}
+
+impl Importer for Ics {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn url(&self, config: &SourceConfig, _window: &DateWindow) -> Result<String, Report> {
+        if let Some(url) = &config.url {
+            Ok(url.to_owned())
+        } else {
+            bail!(
+                "No URL configured for {}, so the iCalendar file must be given with --input.",
+                self.name
+            );
+        }
+    }
+
+    fn convert(&self, raw: &str) -> Result<Imported, Report> {
+        let unfolded = unfold(raw);
+        let calendar = read_calendar(&unfolded).map_err(|e| eyre!(e))?;
+        // Floating times are in the timezone of the calendar, if it has one.
+        let calendar_timezone = find_property(&calendar.properties, "X-WR-TIMEZONE")
+            .and_then(|property| property.val.as_str().parse().ok())
+            .or(self.defaults.timezone);
+
+        let mut events = vec![];
+        let mut warnings = vec![];
+        for component in &calendar.components {
+            if component.name.as_str() != "VEVENT" {
+                continue;
+            }
+            let description = describe(component);
+            match self.convert_event(component, calendar_timezone) {
+                Ok(event) => {
+                    if event.cancelled {
+                        warnings.push(ImportWarning::new(
+                            WarningKind::Cancelled,
+                            &description,
+                            None,
+                        ));
+                    }
+                    if find_property(&component.properties, "RRULE").is_some() {
+                        warnings.push(ImportWarning::new(
+                            WarningKind::Inconsistent,
+                            &description,
+                            Some("repeats, but only the first occurrence was imported"),
+                        ));
+                    }
+                    events.push(event);
+                }
+                Err(reason) => warnings.push(ImportWarning::new(
+                    WarningKind::Dropped,
+                    &description,
+                    Some(&reason.to_string()),
+                )),
+            }
+        }
+        events.sort_by_key(|event| event.start_time_sort_key());
+
+        Ok(Imported {
+            events: Events { events },
+            warnings,
+        })
+    }
+}
+
+impl Ics {
+    fn convert_event(
+        &self,
+        component: &Component,
+        calendar_timezone: Option<Tz>,
+    ) -> Result<Event, Report> {
+        if self.defaults.styles.is_empty() {
+            bail!("no styles configured");
+        }
+        let name = text_property(component, "SUMMARY").ok_or_else(|| eyre!("no summary"))?;
+        let details = text_property(component, "DESCRIPTION")
+            .map(|details| details.trim().to_owned())
+            .filter(|details| !details.is_empty());
+        let links = text_property(component, "URL").into_iter().collect();
+        let cancelled = text_property(component, "STATUS").as_deref() == Some("CANCELLED");
+
+        let start = find_property(&component.properties, "DTSTART")
+            .ok_or_else(|| eyre!("no start time"))?;
+        let start = parse_time(start, calendar_timezone)?;
+        let end = if let Some(end) = find_property(&component.properties, "DTEND") {
+            Some(parse_time(end, calendar_timezone)?)
+        } else if let Some(duration) = find_property(&component.properties, "DURATION") {
+            let duration = parse_duration(duration.val.as_str())
+                .ok_or_else(|| eyre!("invalid duration {}", duration.val.as_str()))?;
+            Some(start.add(duration))
+        } else {
+            None
+        };
+        let (time, timezone) = match (start, end) {
+            (IcsTime::Date(start_date), None) => (
+                EventTime::DateOnly {
+                    start_date,
+                    end_date: start_date,
+                },
+                calendar_timezone,
+            ),
+            (IcsTime::Date(start_date), Some(IcsTime::Date(end_date))) => (
+                EventTime::DateOnly {
+                    start_date,
+                    // The end date is exclusive in iCalendar, but inclusive for us.
+                    end_date: (end_date - Duration::days(1)).max(start_date),
+                },
+                calendar_timezone,
+            ),
+            (IcsTime::DateTime(start, timezone), None) => {
+                (EventTime::DateTime { start, end: start }, timezone)
+            }
+            (IcsTime::DateTime(start, timezone), Some(IcsTime::DateTime(end, _))) => {
+                (EventTime::DateTime { start, end }, timezone)
+            }
+            _ => bail!("start and end must both be dates or both be times"),
+        };
+
+        let location = text_property(component, "LOCATION").unwrap_or_default();
+        let mut location = parse_location(&location);
+        let country = location
+            .country
+            .or_else(|| self.defaults.country.clone())
+            .ok_or_else(|| eyre!("no country"))?;
+        let city = location
+            .city
+            .or_else(|| self.defaults.city.clone())
+            .ok_or_else(|| eyre!("no city"))?;
+        if let Some((latitude, longitude)) = find_property(&component.properties, "GEO")
+            .and_then(|geo| geo.val.as_str().split_once(';'))
+        {
+            location.venue.latitude = latitude.parse().ok();
+            location.venue.longitude = longitude.parse().ok();
+        }
+        let venue = if location.venue == Venue::default() {
+            None
+        } else {
+            Some(location.venue)
+        };
+
+        // Occurrences of a recurring event which have been changed share its UID, so are told
+        // apart by their recurrence ID.
+        let uid = text_property(component, "UID").map(|uid| {
+            match find_property(&component.properties, "RECURRENCE-ID") {
+                Some(recurrence_id) => format!("{} {}", uid, recurrence_id.val.as_str()),
+                None => uid,
+            }
+        });
+
+        let mut event = Event {
+            id: None,
+            name,
+            details,
+            links,
+            time,
+            timezone,
+            country,
+            city,
+            region: None,
+            styles: self.defaults.styles.clone(),
+            workshop: self.defaults.workshop,
+            social: self.defaults.social,
+            bands: vec![],
+            callers: vec![],
+            teachers: vec![],
+            price: None,
+            organisation: self.defaults.organisation.clone(),
+            cancelled,
+            tentative: false,
+            venue,
+            schedule: vec![],
+            band_details: vec![],
+            recurrence: None,
+            source: None,
+        };
+        // Events without a UID are identified by their first link, or their name if they have no
+        // links, and their date.
+        let id = uid.unwrap_or_else(|| {
+            let link = event.links.first().unwrap_or(&event.name);
+            format!("{} {}", link, event.start_date())
+        });
+        event.source = Some(Source {
+            importer: self.name.clone(),
+            id,
+            fetched: None,
+            licence: None,
+            attribution: None,
+        });
+        Ok(event)
+    }
+}
+
+/// A DTSTART or DTEND value.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum IcsTime {
+    Date(NaiveDate),
+    /// A time, along with the timezone it was given in if any.
+    DateTime(DateTime<FixedOffset>, Option<Tz>),
+}
+
+impl IcsTime {
+    /// Adds the given duration. For dates, the result is the exclusive end date of an event lasting
+    /// the given number of whole days.
+    fn add(self, duration: Duration) -> Self {
+        match self {
+            Self::Date(date) => Self::Date(date + Duration::days(duration.num_days())),
+            Self::DateTime(time, timezone) => Self::DateTime(time + duration, timezone),
+        }
+    }
+}
+
+/// Parses an iCalendar DURATION value such as "PT1H30M", "P2D" or "P1W".
+fn parse_duration(value: &str) -> Option<Duration> {
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let value = value.strip_prefix('P')?;
+    let mut duration = Duration::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' if number.is_empty() && !in_time => in_time = true,
+            _ => {
+                let amount: i64 = number.parse().ok()?;
+                number.clear();
+                duration = duration
+                    + match (c, in_time) {
+                        ('W', false) => Duration::weeks(amount),
+                        ('D', false) => Duration::days(amount),
+                        ('H', true) => Duration::hours(amount),
+                        ('M', true) => Duration::minutes(amount),
+                        ('S', true) => Duration::seconds(amount),
+                        _ => return None,
+                    };
+            }
+        }
+    }
+    if !number.is_empty() {
+        return None;
+    }
+    Some(if negative { -duration } else { duration })
+}
+
+/// Parses a DTSTART or DTEND property, using the given timezone for floating times.
+fn parse_time(property: &Property, calendar_timezone: Option<Tz>) -> Result<IcsTime, Report> {
+    let value = property.val.as_str();
+    let parameter = |key: &str| {
+        property
+            .params
+            .iter()
+            .find(|param| param.key.as_str() == key)
+            .and_then(|param| param.val.as_ref())
+            .map(|val| val.as_str())
+    };
+
+    if parameter("VALUE") == Some("DATE") || value.len() == 8 {
+        return Ok(IcsTime::Date(NaiveDate::parse_from_str(value, "%Y%m%d")?));
+    }
+    if let Some(utc) = value.strip_suffix('Z') {
+        let time = Utc.from_utc_datetime(&NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")?);
+        return Ok(match calendar_timezone {
+            Some(timezone) => {
+                IcsTime::DateTime(fixed_offset(time.with_timezone(&timezone)), Some(timezone))
+            }
+            None => IcsTime::DateTime(time.into(), None),
+        });
+    }
+
+    let local = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")?;
+    let timezone: Tz = if let Some(tzid) = parameter("TZID") {
+        tzid.parse()
+            .map_err(|_| eyre!("unknown timezone {}", tzid))?
+    } else if let Some(timezone) = calendar_timezone {
+        timezone
+    } else {
+        bail!("floating time with no timezone");
+    };
+    let time = timezone
+        .from_local_datetime(&local)
+        .single()
+        .ok_or_else(|| eyre!("ambiguous or invalid local time {}", local))?;
+    Ok(IcsTime::DateTime(fixed_offset(time), Some(timezone)))
+}
+
+fn fixed_offset(time: DateTime<Tz>) -> DateTime<FixedOffset> {
+    time.with_timezone(&time.offset().fix())
+}
+
+fn find_property<'a, 'b>(properties: &'b [Property<'a>], name: &str) -> Option<&'b Property<'a>> {
+    properties
+        .iter()
+        .find(|property| property.name.as_str() == name)
+}
+
+/// Gets the value of the given property with escape sequences replaced, if it is present.
+fn text_property(component: &Component, name: &str) -> Option<String> {
+    find_property(&component.properties, name).map(|property| unescape(property.val.as_str()))
+}
+
+/// Replaces the escape sequences in an iCalendar TEXT value.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(other) => unescaped.push(other),
+                None => {}
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+/// Describes the given VEVENT for a warning.
+fn describe(component: &Component) -> String {
+    let start = find_property(&component.properties, "DTSTART")
+        .map(|start| start.val.as_str())
+        .unwrap_or_default();
+    let summary = text_property(component, "SUMMARY").unwrap_or_default();
+    format!("{} {}", start, summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        format::events_yaml,
+        importers::{
+            sync::{sync_events, MissingPolicy},
+            upstream_id,
+        },
+        model::dancestyle::DanceStyle,
+    };
+
+    #[test]
+    fn convert_fixture() {
+        let config = SourceConfig {
+            defaults: EventDefaults {
+                styles: vec![DanceStyle::Contra],
+                organisation: Some("Oxford Contra".to_string()),
+                country: Some("UK".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let ics = Ics::new("oxford-contra", &config);
+        let imported = ics.convert(include_str!("testdata/feed.ics")).unwrap();
+        assert_eq!(
+            events_yaml(&imported.events).unwrap(),
+            include_str!("testdata/feed.yaml")
+        );
+        assert_eq!(
+            imported
+                .warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "Cancelled: 20300201 Cancelled dance",
+                "Inconsistent: 20300215T193000 Weekly class (repeats, but only the first occurrence was imported)",
+                "Dropped: 20300301T200000Z Somewhere (no city)",
+            ]
+        );
+
+        let mut event = imported.events.events[0].clone();
+        assert_eq!(ics.source_id(&event).unwrap(), "contra-2030-01@example.org");
+        event.source = None;
+        assert_eq!(ics.source_id(&event), None);
+    }
+
+    #[test]
+    fn local_events_kept() {
+        let config = SourceConfig {
+            defaults: EventDefaults {
+                styles: vec![DanceStyle::Contra],
+                country: Some("UK".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let ics = Ics::new("oxford-contra", &config);
+        let imported = ics.convert(include_str!("testdata/feed.ics")).unwrap();
+        let local = Event {
+            name: "Local dance".to_string(),
+            links: vec!["https://example.org/local".to_string()],
+            time: imported.events.events[0].time.clone(),
+            country: "UK".to_string(),
+            city: "Oxford".to_string(),
+            ..Default::default()
+        };
+        let existing = Events {
+            events: vec![local.clone()],
+        };
+
+        let sync = sync_events(
+            &existing,
+            None,
+            &imported.events,
+            |event| upstream_id(&ics, event),
+            MissingPolicy::Remove,
+            &DateWindow::default(),
+        )
+        .unwrap();
+        assert_eq!(sync.events[0], local);
+        assert_eq!(sync.missing, 0);
+        assert_eq!(sync.added, imported.events.events.len());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(
+            parse_duration("PT1H30M"),
+            Some(Duration::hours(1) + Duration::minutes(30))
+        );
+        assert_eq!(parse_duration("P2D"), Some(Duration::days(2)));
+        assert_eq!(
+            parse_duration("P1DT12H"),
+            Some(Duration::days(1) + Duration::hours(12))
+        );
+        assert_eq!(parse_duration("-P1W"), Some(-Duration::weeks(1)));
+        assert_eq!(parse_duration("P1H"), None);
+        assert_eq!(parse_duration("PT5"), None);
+        assert_eq!(parse_duration("1H"), None);
+    }
+}
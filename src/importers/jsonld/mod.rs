@@ -0,0 +1,497 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod types;
+
+use self::types::{Address, Country, Location, Offer, OneOrMany};
+use super::{
+    location::{normalise_country, parse_location},
+    DateWindow, ImportWarning, Imported, Importer, WarningKind,
+};
+use crate::{
+    config::{EventDefaults, SourceConfig},
+    model::{
+        event::{Event, EventTime, Venue},
+        events::Events,
+        price::{Price, PriceTier},
+        source::Source,
+    },
+};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use eyre::{bail, eyre, Report};
+use serde_json::Value;
+
+/// The name of the importer for web pages which aren't configured as a named source.
+pub const GENERIC_NAME: &str = "jsonld";
+
+/// The schema.org types which are imported as events.
+const EVENT_TYPES: [&str; 2] = ["Event", "DanceEvent"];
+
+/// Formats in which schema.org dates with times may be given, with and without a UTC offset.
+const OFFSET_FORMATS: [&str; 3] = [
+    "%Y-%m-%dT%H:%M:%S%.f%:z",
+    "%Y-%m-%dT%H:%M%:z",
+    "%Y-%m-%dT%H:%M:%S%.fZ",
+];
+const LOCAL_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M"];
+
+/// Imports schema.org events embedded as JSON-LD in web pages. As schema.org has no fields for
+/// dance styles and so on, these are taken from the configured defaults for the source.
+pub struct JsonLd {
+    name: String,
+    description: String,
+    defaults: EventDefaults,
+}
+
+impl JsonLd {
+    pub fn new(name: &str, config: &SourceConfig) -> Self {
+        let description = if let Some(url) = &config.url {
+            format!("schema.org events in the web page {}", url)
+        } else {
+            "schema.org events in web pages, with defaults from the config file".to_string()
+        };
+        Self {
+            name: name.to_owned(),
+            description,
+            defaults: config.defaults.clone(),
+        }
+    }
+}
+
+impl Importer for JsonLd {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn url(&self, config: &SourceConfig, _window: &DateWindow) -> Result<String, Report> {
+        if let Some(url) = &config.url {
+            Ok(url.to_owned())
+        } else {
+            bail!(
+                "No URL configured for {}, so the web pages must be given with --input.",
+                self.name
+            );
+        }
+    }
+
+    fn convert(&self, raw: &str) -> Result<Imported, Report> {
+        let mut events = vec![];
+        let mut warnings = vec![];
+        for block in script_blocks(raw) {
+            let value: Value = match serde_json::from_str(block) {
+                Ok(value) => value,
+                Err(e) => {
+                    warnings.push(ImportWarning::new(
+                        WarningKind::Inconsistent,
+                        "JSON-LD block",
+                        Some(&format!("invalid JSON: {}", e)),
+                    ));
+                    continue;
+                }
+            };
+            for object in event_objects(&value) {
+                let description = describe(object);
+                let result = serde_json::from_value(object.to_owned())
+                    .map_err(Report::from)
+                    .and_then(|event| self.convert_event(&event));
+                match result {
+                    Ok(event) => {
+                        if event.cancelled {
+                            warnings.push(ImportWarning::new(
+                                WarningKind::Cancelled,
+                                &description,
+                                None,
+                            ));
+                        }
+                        events.push(event);
+                    }
+                    Err(reason) => warnings.push(ImportWarning::new(
+                        WarningKind::Dropped,
+                        &description,
+                        Some(&reason.to_string()),
+                    )),
+                }
+            }
+        }
+        events.sort_by_key(|event| event.start_time_sort_key());
+
+        Ok(Imported {
+            events: Events { events },
+            warnings,
+        })
+    }
+}
+
+impl JsonLd {
+    fn convert_event(&self, event: &types::Event) -> Result<Event, Report> {
+        if self.defaults.styles.is_empty() {
+            bail!("no styles configured");
+        }
+        let name = event.name.clone().ok_or_else(|| eyre!("no name"))?;
+        let details = event
+            .description
+            .as_deref()
+            .map(str::trim)
+            .filter(|details| !details.is_empty())
+            .map(ToOwned::to_owned);
+        let links = event.url.iter().cloned().collect();
+        let status = event.event_status.as_deref().unwrap_or_default();
+        let cancelled = status.ends_with("EventCancelled");
+        // A postponed event doesn't have a new date yet, so the date given may not be right.
+        let tentative = status.ends_with("EventPostponed");
+
+        let start = event
+            .start_date
+            .as_deref()
+            .ok_or_else(|| eyre!("no start date"))?;
+        let start = parse_date_time(start, self.defaults.timezone)?;
+        let end = event
+            .end_date
+            .as_deref()
+            .map(|end| parse_date_time(end, self.defaults.timezone))
+            .transpose()?
+            .unwrap_or(start);
+        let time = match (start, end) {
+            (DateOrTime::DateTime(start), DateOrTime::DateTime(end)) => {
+                EventTime::DateTime { start, end }
+            }
+            _ => EventTime::DateOnly {
+                start_date: start.date(),
+                end_date: end.date(),
+            },
+        };
+
+        let mut location = match event.location.first() {
+            None => Default::default(),
+            Some(Location::Text(text)) => parse_location(text),
+            Some(Location::Place(place)) => {
+                let mut location = match &place.address {
+                    None => Default::default(),
+                    Some(Address::Text(text)) => parse_location(text),
+                    Some(Address::Postal(address)) => super::location::Location {
+                        venue: Venue {
+                            street: address.street_address.clone(),
+                            postcode: address.postal_code.clone(),
+                            ..Default::default()
+                        },
+                        city: address.address_locality.clone(),
+                        country: address.address_country.as_ref().map(|country| {
+                            let name = match country {
+                                Country::Text(name) | Country::Named { name } => name,
+                            };
+                            normalise_country(name)
+                                .map(ToOwned::to_owned)
+                                .unwrap_or_else(|| name.to_owned())
+                        }),
+                    },
+                };
+                if place.name.is_some() {
+                    location.venue.name = place.name.clone();
+                }
+                if let Some(geo) = &place.geo {
+                    location.venue.latitude = geo.latitude.value();
+                    location.venue.longitude = geo.longitude.value();
+                }
+                location
+            }
+        };
+        let country = location
+            .country
+            .take()
+            .or_else(|| self.defaults.country.clone())
+            .ok_or_else(|| eyre!("no country"))?;
+        let city = location
+            .city
+            .take()
+            .or_else(|| self.defaults.city.clone())
+            .ok_or_else(|| eyre!("no city"))?;
+        let venue = if location.venue == Venue::default() {
+            None
+        } else {
+            Some(location.venue)
+        };
+
+        let mut event = Event {
+            id: None,
+            name,
+            details,
+            links,
+            time,
+            timezone: self.defaults.timezone,
+            country,
+            city,
+            region: None,
+            styles: self.defaults.styles.clone(),
+            workshop: self.defaults.workshop,
+            social: self.defaults.social,
+            bands: event
+                .performer
+                .iter()
+                .map(|performer| performer.name().to_owned())
+                .collect(),
+            callers: vec![],
+            teachers: vec![],
+            price: convert_offers(&event.offers),
+            organisation: event
+                .organizer
+                .first()
+                .map(|organizer| organizer.name().to_owned())
+                .or_else(|| self.defaults.organisation.clone()),
+            cancelled,
+            tentative,
+            venue,
+            schedule: vec![],
+            band_details: vec![],
+            recurrence: None,
+            source: None,
+        };
+        // Events are identified by their first link, or their name if they have no links, and
+        // their date.
+        let link = event.links.first().unwrap_or(&event.name);
+        event.source = Some(Source {
+            importer: self.name.clone(),
+            id: format!("{} {}", link, event.start_date()),
+            fetched: None,
+            licence: None,
+            attribution: None,
+        });
+        Ok(event)
+    }
+}
+
+/// Finds the contents of all JSON-LD script elements in the given HTML.
+fn script_blocks(html: &str) -> Vec<&str> {
+    // ASCII lowercasing doesn't change byte offsets, so they can be used to index the original.
+    let lowercase = html.to_ascii_lowercase();
+    let mut blocks = vec![];
+    let mut position = 0;
+    while let Some(start) = lowercase[position..].find("<script") {
+        let tag_start = position + start;
+        let tag_end = match lowercase[tag_start..].find('>') {
+            Some(end) => tag_start + end + 1,
+            None => break,
+        };
+        let close = match lowercase[tag_end..].find("</script") {
+            Some(close) => tag_end + close,
+            None => break,
+        };
+        if lowercase[tag_start..tag_end].contains("application/ld+json") {
+            blocks.push(&html[tag_end..close]);
+        }
+        position = close;
+    }
+    blocks
+}
+
+/// Finds all objects of an event type in the given JSON-LD, including within lists and graphs.
+fn event_objects(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Array(values) => values.iter().flat_map(event_objects).collect(),
+        Value::Object(object) => {
+            if let Some(graph) = object.get("@graph") {
+                event_objects(graph)
+            } else if object.get("@type").is_some_and(is_event_type) {
+                vec![value]
+            } else {
+                vec![]
+            }
+        }
+        _ => vec![],
+    }
+}
+
+/// Returns whether the given `@type` value includes an event type, with or without the schema.org
+/// prefix.
+fn is_event_type(types: &Value) -> bool {
+    let is_event = |value: &Value| {
+        value.as_str().is_some_and(|name| {
+            let name = name.rsplit(['/', ':']).next().unwrap_or(name);
+            EVENT_TYPES.contains(&name)
+        })
+    };
+    match types {
+        Value::Array(values) => values.iter().any(is_event),
+        value => is_event(value),
+    }
+}
+
+/// A schema.org date or date with time.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum DateOrTime {
+    Date(NaiveDate),
+    DateTime(DateTime<FixedOffset>),
+}
+
+impl DateOrTime {
+    fn date(&self) -> NaiveDate {
+        match self {
+            Self::Date(date) => *date,
+            Self::DateTime(time) => time.date().naive_local(),
+        }
+    }
+}
+
+/// Parses an ISO 8601 date or date with time. Times without a UTC offset are taken to be in the
+/// given timezone, and times with one are converted to it.
+fn parse_date_time(value: &str, timezone: Option<Tz>) -> Result<DateOrTime, Report> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(DateOrTime::Date(date));
+    }
+    let time = if let Some(time) = OFFSET_FORMATS.iter().find_map(|format| {
+        DateTime::parse_from_str(value, format).ok().or_else(|| {
+            // `%Z` can't be parsed, so parse a trailing Z as UTC.
+            NaiveDateTime::parse_from_str(value, format)
+                .ok()
+                .map(|time| FixedOffset::east(0).from_utc_datetime(&time))
+        })
+    }) {
+        match timezone {
+            Some(timezone) => {
+                let local = time.with_timezone(&timezone);
+                local.with_timezone(&local.offset().fix())
+            }
+            None => time,
+        }
+    } else if let Some(local) = LOCAL_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    {
+        let timezone = timezone.ok_or_else(|| eyre!("time {} has no timezone", value))?;
+        let time = timezone
+            .from_local_datetime(&local)
+            .single()
+            .ok_or_else(|| eyre!("ambiguous or invalid local time {}", value))?;
+        time.with_timezone(&time.offset().fix())
+    } else {
+        bail!("invalid date {}", value);
+    };
+    Ok(DateOrTime::DateTime(time))
+}
+
+/// Converts schema.org offers to a price. A single offer gives the price or price range, while
+/// several offers with names give price tiers.
+fn convert_offers(offers: &OneOrMany<Offer>) -> Option<Price> {
+    let offers: Vec<&Offer> = offers.iter().collect();
+    let currency = offers.iter().find_map(|offer| offer.price_currency.clone());
+    let amounts: Vec<f64> = offers
+        .iter()
+        .filter_map(|offer| offer.price.as_ref().or(offer.low_price.as_ref())?.value())
+        .collect();
+    if amounts.is_empty() {
+        return None;
+    }
+    if amounts.iter().all(|&amount| amount == 0.0) {
+        return Some(Price::free());
+    }
+
+    let mut price = Price {
+        currency,
+        ..Default::default()
+    };
+    if let [offer] = offers.as_slice() {
+        price.min = Some(amounts[0]);
+        price.max = offer
+            .high_price
+            .as_ref()
+            .and_then(|high| high.value())
+            .filter(|&high| high != amounts[0]);
+    } else if offers.iter().all(|offer| offer.name.is_some()) {
+        price.tiers = offers
+            .iter()
+            .filter_map(|offer| {
+                Some(PriceTier {
+                    name: offer.name.clone()?,
+                    amount: offer.price.as_ref()?.value()?,
+                })
+            })
+            .collect();
+    } else {
+        price.min = amounts.iter().copied().reduce(f64::min);
+        price.max = amounts
+            .iter()
+            .copied()
+            .reduce(f64::max)
+            .filter(|&max| Some(max) != price.min);
+    }
+    Some(price)
+}
+
+/// Describes the given JSON-LD event for a warning.
+fn describe(event: &Value) -> String {
+    let field = |name| event.get(name).and_then(Value::as_str).unwrap_or_default();
+    format!("{} {}", field("startDate"), field("name"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{format::events_yaml, model::dancestyle::DanceStyle};
+
+    #[test]
+    fn convert_fixture() {
+        let config = SourceConfig {
+            defaults: EventDefaults {
+                styles: vec![DanceStyle::Balfolk],
+                timezone: Some(chrono_tz::Europe::Amsterdam),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let imported = JsonLd::new("bal-venue", &config)
+            .convert(include_str!("testdata/page.html"))
+            .unwrap();
+        assert_eq!(
+            events_yaml(&imported.events).unwrap(),
+            include_str!("testdata/page.yaml")
+        );
+        assert_eq!(
+            imported
+                .warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "Cancelled: 2030-05-02T20:00 Cancelled bal",
+                "Dropped: 2030-06-01 Somewhere (no country)",
+            ]
+        );
+    }
+
+    #[test]
+    fn source_ids() {
+        let jsonld = JsonLd::new("bal-venue", &SourceConfig::default());
+        let mut event = Event {
+            name: "Bal".to_string(),
+            links: vec!["https://example.org/bal".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(jsonld.source_id(&event), None);
+        event.source = Some(Source {
+            importer: "bal-venue".to_string(),
+            id: "https://example.org/bal 2030-05-01".to_string(),
+            fetched: None,
+            licence: None,
+            attribution: None,
+        });
+        assert_eq!(
+            jsonld.source_id(&event).unwrap(),
+            "https://example.org/bal 2030-05-01"
+        );
+    }
+}
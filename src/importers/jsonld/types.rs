@@ -0,0 +1,154 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::Deserialize;
+
+/// A property which may have a single value or a list of values. JSON-LD allows this for most
+/// properties.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        match self {
+            Self::One(value) => std::slice::from_ref(value).iter(),
+            Self::Many(values) => values.iter(),
+        }
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.iter().next()
+    }
+}
+
+impl<T> Default for OneOrMany<T> {
+    fn default() -> Self {
+        Self::Many(vec![])
+    }
+}
+
+/// A number which may be given as either a JSON number or a string.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Number {
+    Number(f64),
+    Text(String),
+}
+
+impl Number {
+    pub fn value(&self) -> Option<f64> {
+        match self {
+            Self::Number(number) => Some(*number),
+            Self::Text(text) => text.trim().parse().ok(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Event {
+    #[serde(rename = "@type")]
+    pub event_type: OneOrMany<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub url: OneOrMany<String>,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    #[serde(default)]
+    pub location: OneOrMany<Location>,
+    #[serde(default)]
+    pub offers: OneOrMany<Offer>,
+    #[serde(default)]
+    pub performer: OneOrMany<Agent>,
+    #[serde(default)]
+    pub organizer: OneOrMany<Agent>,
+    pub event_status: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Location {
+    Text(String),
+    Place(Place),
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Place {
+    pub name: Option<String>,
+    pub address: Option<Address>,
+    pub geo: Option<GeoCoordinates>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Address {
+    Text(String),
+    Postal(PostalAddress),
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PostalAddress {
+    pub street_address: Option<String>,
+    pub address_locality: Option<String>,
+    pub postal_code: Option<String>,
+    pub address_country: Option<Country>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Country {
+    Text(String),
+    Named { name: String },
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct GeoCoordinates {
+    pub latitude: Number,
+    pub longitude: Number,
+}
+
+/// An `Offer` or `AggregateOffer`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Offer {
+    pub name: Option<String>,
+    pub price: Option<Number>,
+    pub low_price: Option<Number>,
+    pub high_price: Option<Number>,
+    pub price_currency: Option<String>,
+}
+
+/// A `Person` or `Organization`, such as a performer or organiser.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Agent {
+    Text(String),
+    Named { name: String },
+}
+
+impl Agent {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Text(name) => name,
+            Self::Named { name } => name,
+        }
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{lint::COUNTRIES, model::event::Venue};
+
+/// The parts of a location given as a single string.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Location {
+    pub venue: Venue,
+    pub city: Option<String>,
+    pub country: Option<String>,
+}
+
+/// Parses a location of the usual form "Venue, Street, Postcode City, Country", any part of which
+/// may be missing. The last part is taken as the country if it is a known country name, and
+/// otherwise as the city.
+pub fn parse_location(location: &str) -> Location {
+    let mut parts: Vec<&str> = location
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect();
+    let mut parsed = Location::default();
+
+    if let Some(country) = parts.last().and_then(|part| normalise_country(part)) {
+        parsed.country = Some(country.to_owned());
+        parts.pop();
+    }
+    if let Some(city_part) = parts.pop() {
+        // Postcodes are the words containing digits, e.g. "9000 Gent" or "Oxford OX1 2AB".
+        let (postcode, city): (Vec<&str>, Vec<&str>) = city_part
+            .split_whitespace()
+            .partition(|word| word.chars().any(|c| c.is_ascii_digit()));
+        if !city.is_empty() {
+            parsed.city = Some(city.join(" "));
+        }
+        if !postcode.is_empty() {
+            parsed.venue.postcode = Some(postcode.join(" "));
+        }
+    }
+    if parts.len() > 1 {
+        parsed.venue.street = Some(parts[1..].join(", "));
+    }
+    if let Some(name) = parts.first() {
+        parsed.venue.name = Some(name.to_string());
+    }
+    parsed
+}
+
+/// Returns the country name as used in events files, if the given string is a country name or
+/// common ISO 3166 code.
+pub fn normalise_country(country: &str) -> Option<&'static str> {
+    match country.to_lowercase().as_str() {
+        "united kingdom" | "great britain" | "england" | "scotland" | "wales"
+        | "northern ireland" | "gb" | "uk" => Some("UK"),
+        "united states" | "united states of america" | "us" => Some("USA"),
+        "at" => Some("Austria"),
+        "be" => Some("Belgium"),
+        "ca" => Some("Canada"),
+        "ch" => Some("Switzerland"),
+        "cz" => Some("Czechia"),
+        "de" => Some("Germany"),
+        "dk" => Some("Denmark"),
+        "es" => Some("Spain"),
+        "fr" => Some("France"),
+        "ie" => Some("Ireland"),
+        "it" => Some("Italy"),
+        "nl" => Some("Netherlands"),
+        "no" => Some("Norway"),
+        "nz" => Some("New Zealand"),
+        "pl" => Some("Poland"),
+        "pt" => Some("Portugal"),
+        "se" => Some("Sweden"),
+        lowercase => COUNTRIES
+            .iter()
+            .find(|name| name.to_lowercase() == lowercase)
+            .copied(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations() {
+        assert_eq!(
+            parse_location("Village Hall, 1 High Street, Oxford OX1 2AB, United Kingdom"),
+            Location {
+                venue: Venue {
+                    name: Some("Village Hall".to_string()),
+                    street: Some("1 High Street".to_string()),
+                    postcode: Some("OX1 2AB".to_string()),
+                    ..Default::default()
+                },
+                city: Some("Oxford".to_string()),
+                country: Some("UK".to_string()),
+            }
+        );
+        assert_eq!(
+            parse_location("Gent"),
+            Location {
+                city: Some("Gent".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -12,5 +12,348 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod csv;
 pub mod folkbalbende;
+pub mod ics;
+pub mod jsonld;
+pub mod location;
+pub mod rules;
+pub mod sync;
 pub mod webfeet;
+
+use crate::{
+    config::{ImportersConfig, SourceConfig, SourceKind},
+    model::{event::Event, events::Events, source::Source},
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use eyre::{bail, Report, WrapErr};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    fs::read_to_string,
+    io::{stdin, Read},
+};
+
+/// A source from which events can be imported.
+pub trait Importer {
+    /// The name by which the source is chosen on the command line.
+    fn name(&self) -> &str;
+
+    /// A short description of the source.
+    fn description(&self) -> &str;
+
+    /// The URL from which to fetch the raw event data, for events in the given date window.
+    fn url(&self, config: &SourceConfig, window: &DateWindow) -> Result<String, Report>;
+
+    /// Converts the raw event data fetched from the source into events, along with warnings about
+    /// any upstream events which need attention or couldn't be converted.
+    fn convert(&self, raw: &str) -> Result<Imported, Report>;
+
+    /// Returns an ID for an event which was imported from this source, which stays the same between
+    /// imports, or `None` if the event didn't come from this source.
+    ///
+    /// By default this is the ID recorded in the event's source, so importers which use it must
+    /// record the source of each event when converting it.
+    fn source_id(&self, event: &Event) -> Option<String> {
+        event
+            .source
+            .as_ref()
+            .filter(|source| source.importer == self.name())
+            .map(|source| source.id.to_owned())
+    }
+
+    /// Credit to show for events imported from this source, if it asks for any.
+    fn attribution(&self) -> Option<&str> {
+        None
+    }
+
+    /// The licence under which the source's data may be used, if known.
+    fn licence(&self) -> Option<&str> {
+        None
+    }
+
+    /// The rules to apply to events from this source if no rules file is configured, in TOML.
+    fn default_rules(&self) -> &str {
+        ""
+    }
+}
+
+/// Returns the ID of an event in the upstream source of the given importer, or `None` if it didn't
+/// come from that source. Events which were imported with their source recorded are identified by
+/// the recorded ID, so that they still match after the fields the ID was derived from have changed.
+pub fn upstream_id(importer: &dyn Importer, event: &Event) -> Option<String> {
+    match &event.source {
+        Some(source) if source.importer == importer.name() => Some(source.id.to_owned()),
+        Some(_) => None,
+        None => importer.source_id(event),
+    }
+}
+
+/// The result of importing events from a source.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Imported {
+    pub events: Events,
+    pub warnings: Vec<ImportWarning>,
+}
+
+impl Imported {
+    /// Drops any events which aren't valid, e.g. because no rule has set a country which the source
+    /// doesn't give, adding a warning for each.
+    pub fn drop_invalid(&mut self) {
+        let warnings = &mut self.warnings;
+        self.events.events.retain(|event| {
+            let problems = event.validate();
+            if !problems.is_empty() {
+                warnings.push(ImportWarning::new(
+                    WarningKind::Dropped,
+                    format!("{} {}", event.start_date(), event.name),
+                    Some(&problems.join(" ")),
+                ));
+            }
+            problems.is_empty()
+        });
+    }
+
+    /// Records the importer, upstream ID and the given fetch time as the source of each event
+    /// which has a source ID.
+    pub fn set_sources(
+        &mut self,
+        importer: &dyn Importer,
+        config: &SourceConfig,
+        fetched: DateTime<Utc>,
+    ) {
+        for event in &mut self.events.events {
+            if let Some(id) = importer.source_id(event) {
+                event.source = Some(Source {
+                    importer: importer.name().to_owned(),
+                    id,
+                    fetched: Some(fetched),
+                    licence: config
+                        .licence
+                        .clone()
+                        .or_else(|| importer.licence().map(ToOwned::to_owned)),
+                    attribution: config
+                        .attribution
+                        .clone()
+                        .or_else(|| importer.attribution().map(ToOwned::to_owned)),
+                });
+            }
+        }
+    }
+}
+
+/// A range of dates for which to import events. Either end may be unbounded.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct DateWindow {
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+}
+
+impl DateWindow {
+    /// Gets the date window configured for a source, relative to the given date.
+    pub fn for_source(config: &SourceConfig, today: NaiveDate) -> Self {
+        Self {
+            start: config.days_before.map(|days| today - Duration::days(days)),
+            end: config.days_after.map(|days| today + Duration::days(days)),
+        }
+    }
+
+    /// Returns whether any part of the given event is within the window.
+    pub fn contains(&self, event: &Event) -> bool {
+        self.start.is_none_or(|start| event.end_date() >= start)
+            && self.end.is_none_or(|end| event.start_date() <= end)
+    }
+}
+
+/// A warning about an upstream event.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ImportWarning {
+    pub kind: WarningKind,
+    /// The ID of the upstream record, for sources which give one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// A description of the upstream event, such as its dates and name.
+    pub event: String,
+    /// More details of the problem, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// The kind of an `ImportWarning`.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// The event has been cancelled upstream, but was still imported.
+    Cancelled,
+    /// The event hasn't been checked by the upstream moderators, so wasn't imported.
+    Unchecked,
+    /// The event wasn't imported, for the given reason.
+    Dropped,
+    /// The upstream data for the event is inconsistent, so may not have been imported correctly.
+    Inconsistent,
+}
+
+impl ImportWarning {
+    pub fn new(kind: WarningKind, event: impl Into<String>, reason: Option<&str>) -> Self {
+        Self {
+            kind,
+            id: None,
+            event: event.into(),
+            reason: reason.map(ToOwned::to_owned),
+        }
+    }
+
+    /// Sets the ID of the upstream record which the warning is about.
+    pub fn with_id(self, id: impl Into<String>) -> Self {
+        Self {
+            id: Some(id.into()),
+            ..self
+        }
+    }
+}
+
+impl Display for ImportWarning {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.kind)?;
+        if let Some(id) = &self.id {
+            write!(f, "[{}] ", id)?;
+        }
+        f.write_str(&self.event)?;
+        if let Some(reason) = &self.reason {
+            write!(f, " ({})", reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// A summary of the result of an import, which can be output as JSON.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct ImportReport {
+    /// The name of the importer.
+    pub importer: String,
+    /// The number of events imported.
+    pub imported: usize,
+    /// The number of converted events which were left out because they were outside the date
+    /// window.
+    pub outside_window: usize,
+    /// The number of imported events which were changed by rules.
+    pub changed_by_rules: usize,
+    pub warnings: Vec<ImportWarning>,
+}
+
+impl ImportReport {
+    /// Summarises the report on a single line, with the number of warnings of each kind.
+    pub fn summary(&self) -> String {
+        let mut counts: BTreeMap<WarningKind, usize> = BTreeMap::new();
+        for warning in &self.warnings {
+            *counts.entry(warning.kind).or_default() += 1;
+        }
+        let mut counts: Vec<String> = counts
+            .iter()
+            .map(|(kind, count)| format!("{} {}", count, kind.to_string().to_lowercase()))
+            .collect();
+        if self.outside_window > 0 {
+            counts.push(format!("{} outside date window", self.outside_window));
+        }
+        if self.changed_by_rules > 0 {
+            counts.push(format!("{} changed by rules", self.changed_by_rules));
+        }
+        format!(
+            "Imported {} events from {}.{}",
+            self.imported,
+            self.importer,
+            if counts.is_empty() {
+                String::new()
+            } else {
+                format!(" Warnings: {}.", counts.join(", "))
+            }
+        )
+    }
+}
+
+impl Display for WarningKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Cancelled => "Cancelled",
+            Self::Unchecked => "Not checked",
+            Self::Dropped => "Dropped",
+            Self::Inconsistent => "Inconsistent",
+        })
+    }
+}
+
+/// Gets all available importers: the built-in ones, and any others configured in the given config.
+pub fn all_importers(config: &ImportersConfig) -> Vec<Box<dyn Importer>> {
+    let mut importers: Vec<Box<dyn Importer>> = vec![
+        Box::new(csv::CsvFile),
+        Box::new(folkbalbende::FolkBalBende),
+        Box::new(ics::Ics::new(
+            ics::GENERIC_NAME,
+            &config.source(ics::GENERIC_NAME),
+        )),
+        Box::new(jsonld::JsonLd::new(
+            jsonld::GENERIC_NAME,
+            &config.source(jsonld::GENERIC_NAME),
+        )),
+        Box::new(webfeet::Webfeet::new(&config.source(webfeet::NAME))),
+    ];
+    let mut sources: Vec<_> = config.sources.iter().collect();
+    sources.sort_by_key(|(name, _)| name.to_owned());
+    for (name, source) in sources {
+        match source.kind {
+            Some(SourceKind::Ics) if name != ics::GENERIC_NAME => {
+                importers.push(Box::new(ics::Ics::new(name, source)))
+            }
+            Some(SourceKind::JsonLd) if name != jsonld::GENERIC_NAME => {
+                importers.push(Box::new(jsonld::JsonLd::new(name, source)))
+            }
+            _ => {}
+        }
+    }
+    importers
+}
+
+/// Gets the importer with the given name.
+pub fn find_importer(name: &str, config: &ImportersConfig) -> Result<Box<dyn Importer>, Report> {
+    if let Some(importer) = all_importers(config)
+        .into_iter()
+        .find(|importer| importer.name() == name)
+    {
+        Ok(importer)
+    } else {
+        bail!("Unknown import source '{}'", name);
+    }
+}
+
+/// Fetches the raw event data for the given importer from its URL.
+pub async fn fetch(
+    importer: &dyn Importer,
+    config: &SourceConfig,
+    window: &DateWindow,
+) -> Result<String, Report> {
+    let url = importer.url(config, window)?;
+    Ok(reqwest::get(&url)
+        .await
+        .wrap_err_with(|| format!("Fetching {}", url))?
+        .text()
+        .await?)
+}
+
+/// Reads raw event data from the given URL, from stdin if the input is "-", or otherwise from a
+/// file.
+pub async fn read_input(input: &str) -> Result<String, Report> {
+    if input.starts_with("http://") || input.starts_with("https://") {
+        Ok(reqwest::get(input)
+            .await
+            .wrap_err_with(|| format!("Fetching {}", input))?
+            .text()
+            .await?)
+    } else if input == "-" {
+        let mut raw = String::new();
+        stdin().read_to_string(&mut raw).wrap_err("Reading stdin")?;
+        Ok(raw)
+    } else {
+        read_to_string(input).wrap_err_with(|| format!("Reading {:?}", input))
+    }
+}
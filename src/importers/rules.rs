@@ -0,0 +1,229 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{ImportWarning, Imported, Importer, WarningKind};
+use crate::{
+    config::SourceConfig,
+    model::{dancestyle::DanceStyle, event::Event},
+};
+use eyre::{Report, WrapErr};
+use serde::Deserialize;
+use std::{fs::read_to_string, mem::take};
+
+/// Rules for dropping or rewriting the events from an importer, to handle exceptions in the
+/// upstream data.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Rules {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<Rule>,
+}
+
+/// A rule which drops or changes the events matching its condition.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Why the rule exists, which is given when it drops an event.
+    pub reason: Option<String>,
+    /// The events to which the rule applies.
+    #[serde(default, rename = "match")]
+    pub condition: Condition,
+    /// Drops the event entirely. Any other actions are ignored.
+    #[serde(default)]
+    pub drop: bool,
+    /// Replaces the dance styles of the event.
+    pub set_styles: Option<Vec<DanceStyle>>,
+    /// Replaces the city of the event.
+    pub set_city: Option<String>,
+    /// Replaces the country of the event.
+    pub set_country: Option<String>,
+    /// Removes all links of the event which start with this prefix.
+    pub strip_link: Option<String>,
+}
+
+/// A condition on an event. All the fields which are given must match; if none are given then all
+/// events match.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Condition {
+    /// The city of the event is exactly this.
+    pub city: Option<String>,
+    /// The name of the event contains this, ignoring case.
+    pub name: Option<String>,
+    /// The organisation of the event is exactly this.
+    pub organisation: Option<String>,
+    /// At least one link of the event starts with this.
+    pub link_prefix: Option<String>,
+}
+
+impl Rules {
+    /// Loads the rules for the given importer, from the file configured for the source if any or
+    /// otherwise the importer's built-in rules.
+    pub fn for_importer(importer: &dyn Importer, config: &SourceConfig) -> Result<Self, Report> {
+        if let Some(path) = &config.rules {
+            let rules = read_to_string(path).wrap_err_with(|| format!("Reading {:?}", path))?;
+            Self::parse(&rules).wrap_err_with(|| format!("Parsing {:?}", path))
+        } else {
+            Self::parse(importer.default_rules())
+                .wrap_err_with(|| format!("Parsing built-in rules for {}", importer.name()))
+        }
+    }
+
+    pub fn parse(rules: &str) -> Result<Self, Report> {
+        Ok(toml::from_str(rules)?)
+    }
+
+    /// Applies the rules in order to each imported event. A warning is added for each event which
+    /// is dropped. Returns the number of events which were changed.
+    pub fn apply(&self, imported: &mut Imported) -> usize {
+        let mut changed = 0;
+        for mut event in take(&mut imported.events.events) {
+            let original = event.clone();
+            let mut dropped_by = None;
+            for rule in &self.rules {
+                if !rule.condition.matches(&event) {
+                    continue;
+                }
+                if rule.drop {
+                    dropped_by = Some(rule);
+                    break;
+                }
+                rule.rewrite(&mut event);
+            }
+            if let Some(rule) = dropped_by {
+                imported.warnings.push(ImportWarning::new(
+                    WarningKind::Dropped,
+                    format!("{} {}", event.start_date(), event.name),
+                    Some(rule.reason.as_deref().unwrap_or("dropped by rule")),
+                ));
+            } else {
+                if event != original {
+                    changed += 1;
+                }
+                imported.events.events.push(event);
+            }
+        }
+        changed
+    }
+}
+
+impl Rule {
+    fn rewrite(&self, event: &mut Event) {
+        if let Some(styles) = &self.set_styles {
+            event.styles = styles.clone();
+        }
+        if let Some(city) = &self.set_city {
+            event.city = city.clone();
+        }
+        if let Some(country) = &self.set_country {
+            event.country = country.clone();
+        }
+        if let Some(prefix) = &self.strip_link {
+            event.links.retain(|link| !link.starts_with(prefix));
+        }
+    }
+}
+
+impl Condition {
+    fn matches(&self, event: &Event) -> bool {
+        self.city.as_ref().is_none_or(|city| &event.city == city)
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|name| event.name.to_lowercase().contains(&name.to_lowercase()))
+            && self
+                .organisation
+                .as_ref()
+                .is_none_or(|organisation| event.organisation.as_ref() == Some(organisation))
+            && self
+                .link_prefix
+                .as_ref()
+                .is_none_or(|prefix| event.links.iter().any(|link| link.starts_with(prefix)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{event::EventTime, events::Events};
+    use chrono::NaiveDate;
+
+    fn event(name: &str, city: &str) -> Event {
+        Event {
+            name: name.to_string(),
+            links: vec![
+                "https://example.com/".to_string(),
+                "https://tracker.example.com/?id=1".to_string(),
+            ],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd(2030, 1, 1),
+                end_date: NaiveDate::from_ymd(2030, 1, 1),
+            },
+            country: String::new(),
+            city: city.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn apply_rules() {
+        let rules = Rules::parse(
+            r#"
+            [[rule]]
+            set_country = "Belgium"
+            strip_link = "https://tracker.example.com/"
+
+            [[rule]]
+            reason = "online"
+            match = { city = "Zoom" }
+            drop = true
+
+            [[rule]]
+            match = { city = "Ghent", name = "CONTRA" }
+            set_city = "Gent"
+            set_styles = ["contra"]
+            "#,
+        )
+        .unwrap();
+        let mut imported = Imported {
+            events: Events {
+                events: vec![
+                    event("Contra dance", "Ghent"),
+                    event("Online bal", "Zoom"),
+                    event("Bal", "Ghent"),
+                ],
+            },
+            warnings: vec![],
+        };
+
+        assert_eq!(rules.apply(&mut imported), 2);
+
+        let mut contra = event("Contra dance", "Gent");
+        contra.links.pop();
+        contra.country = "Belgium".to_string();
+        contra.styles = vec![DanceStyle::Contra];
+        let mut bal = event("Bal", "Ghent");
+        bal.links.pop();
+        bal.country = "Belgium".to_string();
+        assert_eq!(imported.events.events, vec![contra, bal]);
+        assert_eq!(
+            imported
+                .warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["Dropped: 2030-01-01 Online bal (online)"]
+        );
+    }
+}
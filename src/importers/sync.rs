@@ -0,0 +1,487 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::DateWindow;
+use crate::model::{event::Event, events::Events};
+use eyre::{Report, WrapErr};
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+/// What to do with previously imported events which are no longer in the upstream source.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingPolicy {
+    /// Leave the event as it is.
+    Keep,
+    /// Mark the event as cancelled.
+    #[default]
+    Cancel,
+    /// Remove the event from the file.
+    Remove,
+}
+
+/// The result of updating a set of existing events with newly imported events.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sync {
+    /// The updated events.
+    pub events: Vec<Event>,
+    /// For each updated event, the index of the existing event which it came from, if any.
+    pub origins: Vec<Option<usize>>,
+    /// The number of new events which were added.
+    pub added: usize,
+    /// The number of existing events which were changed to match upstream changes.
+    pub updated: usize,
+    /// The number of existing events which are unchanged.
+    pub unchanged: usize,
+    /// Descriptions of fields which were changed upstream but have been kept as they were because
+    /// they had been edited locally.
+    pub overridden: Vec<String>,
+    /// The number of events which are no longer upstream and so were handled according to the
+    /// `MissingPolicy`.
+    pub missing: usize,
+    /// The names of events which differ from upstream but have no base event to compare with, so
+    /// couldn't be updated.
+    pub without_base: Vec<String>,
+    /// The events to record as the base for the next import. These are the imported events, along
+    /// with the base events of any events which are no longer upstream but have been kept, so that
+    /// they can be updated if they come back. Events cancelled because they were missing are
+    /// recorded as cancelled, so that they are uncancelled if they come back.
+    pub base: Vec<Event>,
+}
+
+/// Returns the path of the file which records the events as they were last imported into the
+/// given file, before any local edits. This doesn't have a `.yaml` extension so that it isn't
+/// loaded as part of the events directory.
+pub fn base_path(path: &Path) -> PathBuf {
+    let mut base: OsString = path.as_os_str().to_owned();
+    base.push(".upstream");
+    base.into()
+}
+
+/// Updates the `existing` events with the `imported` events from upstream.
+///
+/// Events are matched by the ID given to them by `source_id`. Events without a source ID are
+/// assumed to have been added locally, and are kept as they are. For matched events, each field is
+/// updated to the upstream value unless it has been edited locally, which is determined by
+/// comparing it to the `base` events from the previous import. If there is no base event then all
+/// fields which differ are assumed to be local edits. New upstream events are added at the end.
+///
+/// Events which have disappeared from upstream are handled according to `missing`, if they are in
+/// the `expected` date window. Events outside it, such as those which are already over, are kept
+/// as they are. Either way their base events are kept for the next import.
+pub fn sync_events(
+    existing: &Events,
+    base: Option<&Events>,
+    imported: &Events,
+    source_id: impl Fn(&Event) -> Option<String>,
+    missing: MissingPolicy,
+    expected: &DateWindow,
+) -> Result<Sync, Report> {
+    let base_by_id: HashMap<String, &Event> = base
+        .iter()
+        .flat_map(|base| &base.events)
+        .filter_map(|event| Some((source_id(event)?, event)))
+        .collect();
+    let mut imported_by_id: HashMap<String, &Event> = HashMap::new();
+    for event in &imported.events {
+        if let Some(id) = source_id(event) {
+            imported_by_id.entry(id).or_insert(event);
+        }
+    }
+
+    let mut sync = Sync::default();
+    let mut matched_ids = HashSet::new();
+    for (index, local) in existing.events.iter().enumerate() {
+        let id = if let Some(id) = source_id(local) {
+            id
+        } else {
+            sync.keep(local.clone(), Some(index));
+            continue;
+        };
+
+        let previous_base = base_by_id.get(&id).copied();
+        if let Some(upstream) = imported_by_id.get(&id) {
+            if previous_base.is_none() && !same_apart_from_fetched(upstream, local) {
+                sync.without_base.push(local.name.clone());
+            }
+            let base = previous_base.unwrap_or(upstream);
+            matched_ids.insert(id);
+            let (merged, overridden) = merge_fields(base, local, upstream)
+                .wrap_err_with(|| format!("Updating event '{}'", local.name))?;
+            for field in overridden {
+                sync.overridden
+                    .push(format!("{} of '{}'", field, local.name));
+            }
+            if same_apart_from_fetched(&merged, local) {
+                sync.keep(local.clone(), Some(index));
+            } else {
+                sync.updated += 1;
+                sync.events.push(merged);
+                sync.origins.push(Some(index));
+            }
+        } else if !expected.contains(local) {
+            sync.keep(local.clone(), Some(index));
+            sync.base.extend(previous_base.cloned());
+        } else {
+            sync.missing += 1;
+            match missing {
+                MissingPolicy::Keep => {
+                    sync.events.push(local.clone());
+                    sync.origins.push(Some(index));
+                    sync.base.extend(previous_base.cloned());
+                }
+                MissingPolicy::Cancel => {
+                    let mut cancelled = local.clone();
+                    cancelled.cancelled = true;
+                    sync.events.push(cancelled);
+                    sync.origins.push(Some(index));
+                    sync.base.extend(previous_base.map(|base| Event {
+                        cancelled: true,
+                        ..base.clone()
+                    }));
+                }
+                MissingPolicy::Remove => {}
+            }
+        }
+    }
+
+    for event in &imported.events {
+        if let Some(id) = source_id(event) {
+            if !matched_ids.insert(id) {
+                continue;
+            }
+        }
+        sync.added += 1;
+        sync.events.push(event.clone());
+        sync.origins.push(None);
+    }
+    sync.base.extend(imported.events.iter().cloned());
+
+    Ok(sync)
+}
+
+impl Sync {
+    fn keep(&mut self, event: Event, origin: Option<usize>) {
+        self.unchanged += 1;
+        self.events.push(event);
+        self.origins.push(origin);
+    }
+}
+
+/// The fields which together give the time of an event, so must be merged as a unit. Merging them
+/// separately could otherwise mix up the fields of a date-only time with those of a time with a time
+/// of day.
+const TIME_FIELDS: [&str; 5] = ["start_date", "end_date", "start", "end", "timezone"];
+
+/// Does a three-way merge of the fields of an event. Fields which are the same in `local` as in
+/// `base` are taken from `upstream`, while fields which have been changed locally are kept. Returns
+/// the merged event and the names of any fields which were changed both locally and upstream. The
+/// time and timezone are merged together as a single field called "time".
+fn merge_fields(
+    base: &Event,
+    local: &Event,
+    upstream: &Event,
+) -> Result<(Event, Vec<String>), Report> {
+    let base = to_mapping(base)?;
+    let local = to_mapping(local)?;
+    let upstream = to_mapping(upstream)?;
+
+    let mut names: Vec<&Value> = vec![];
+    for name in local.iter().chain(upstream.iter()).map(|(key, _)| key) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    let time_fields = |mapping: &Mapping| -> Vec<Option<Value>> {
+        TIME_FIELDS
+            .iter()
+            .map(|&name| mapping.get(&Value::from(name)).cloned())
+            .collect()
+    };
+    let local_time_changed = time_fields(&local) != time_fields(&base);
+    let upstream_time_changed = time_fields(&upstream) != time_fields(&base);
+
+    let mut merged = Mapping::new();
+    let mut overridden = vec![];
+    for name in names {
+        let local_value = local.get(name);
+        let upstream_value = upstream.get(name);
+        let is_time = name
+            .as_str()
+            .is_some_and(|name| TIME_FIELDS.contains(&name));
+        // The source is never edited locally, so always take the latest.
+        let take_upstream = if is_time {
+            !local_time_changed
+        } else {
+            local_value == base.get(name) || name.as_str() == Some("source")
+        };
+        let value = if take_upstream {
+            upstream_value
+        } else {
+            if !is_time && upstream_value != base.get(name) && upstream_value != local_value {
+                overridden.push(name.as_str().unwrap_or_default().to_owned());
+            }
+            local_value
+        };
+        if let Some(value) = value {
+            merged.insert(name.to_owned(), value.to_owned());
+        }
+    }
+    if local_time_changed && upstream_time_changed && time_fields(&local) != time_fields(&upstream)
+    {
+        overridden.push("time".to_owned());
+    }
+    Ok((serde_yaml::from_value(Value::Mapping(merged))?, overridden))
+}
+
+/// Returns whether the two events are the same apart from when their sources were fetched, so
+/// that fetching an event again without any other changes doesn't count as an update.
+fn same_apart_from_fetched(a: &Event, b: &Event) -> bool {
+    let mut a = a.clone();
+    if let (Some(a_source), Some(b_source)) = (&mut a.source, &b.source) {
+        a_source.fetched = b_source.fetched;
+    }
+    &a == b
+}
+
+fn to_mapping(event: &Event) -> Result<Mapping, Report> {
+    match serde_yaml::to_value(event)? {
+        Value::Mapping(mapping) => Ok(mapping),
+        _ => Ok(Mapping::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{dancestyle::DanceStyle, event::EventTime, source::Source};
+    use chrono::{FixedOffset, NaiveDate, TimeZone, Utc};
+
+    fn event(name: &str, day: u32, city: &str) -> Event {
+        Event {
+            name: name.to_string(),
+            links: vec![format!("https://example.com/{}", name)],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd(2022, 3, day),
+                end_date: NaiveDate::from_ymd(2022, 3, day),
+            },
+            city: city.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn source_id(event: &Event) -> Option<String> {
+        event.links.first().cloned()
+    }
+
+    #[test]
+    fn keeps_local_edits() {
+        let base = Events {
+            events: vec![event("a", 1, "Gent"), event("b", 2, "Gent")],
+        };
+        let mut edited = event("a", 1, "Gent centrum");
+        edited.styles.push(DanceStyle::Contra);
+        let mut local_only = event("local", 3, "Brugge");
+        local_only.links.clear();
+        let existing = Events {
+            events: vec![edited, event("b", 2, "Gent"), local_only.clone()],
+        };
+        let mut moved = event("a", 5, "Ghent");
+        moved.bands.push("Band".to_string());
+        let imported = Events {
+            events: vec![moved, event("c", 4, "Gent")],
+        };
+
+        let sync = sync_events(
+            &existing,
+            Some(&base),
+            &imported,
+            source_id,
+            MissingPolicy::Cancel,
+            &DateWindow {
+                start: Some(NaiveDate::from_ymd(2022, 3, 1)),
+                end: None,
+            },
+        )
+        .unwrap();
+
+        let mut expected_a = event("a", 5, "Gent centrum");
+        expected_a.styles.push(DanceStyle::Contra);
+        expected_a.bands.push("Band".to_string());
+        let mut cancelled_b = event("b", 2, "Gent");
+        cancelled_b.cancelled = true;
+        assert_eq!(
+            sync.events,
+            vec![expected_a, cancelled_b, local_only, event("c", 4, "Gent")]
+        );
+        assert_eq!(sync.origins, vec![Some(0), Some(1), Some(2), None]);
+        assert_eq!(sync.overridden, vec!["city of 'a'"]);
+        assert_eq!(
+            (sync.added, sync.updated, sync.unchanged, sync.missing),
+            (1, 1, 1, 1)
+        );
+    }
+
+    #[test]
+    fn time_merged_as_unit() {
+        let base = event("a", 1, "Gent");
+        let mut local = base.clone();
+        local.city = "Gent centrum".to_string();
+        let mut upstream = base.clone();
+        upstream.time = EventTime::DateTime {
+            start: FixedOffset::east(3600).ymd(2022, 3, 1).and_hms(20, 0, 0),
+            end: FixedOffset::east(3600).ymd(2022, 3, 1).and_hms(23, 0, 0),
+        };
+        upstream.timezone = Some(chrono_tz::Europe::Brussels);
+
+        let (merged, overridden) = merge_fields(&base, &local, &upstream).unwrap();
+        assert_eq!(merged.time, upstream.time);
+        assert_eq!(merged.timezone, upstream.timezone);
+        assert_eq!(merged.city, "Gent centrum");
+        assert!(overridden.is_empty());
+
+        // If the time was edited locally then none of it is taken from upstream.
+        let mut local = base.clone();
+        local.time = EventTime::DateOnly {
+            start_date: NaiveDate::from_ymd(2022, 3, 1),
+            end_date: NaiveDate::from_ymd(2022, 3, 2),
+        };
+        let (merged, overridden) = merge_fields(&base, &local, &upstream).unwrap();
+        assert_eq!(merged.time, local.time);
+        assert_eq!(merged.timezone, None);
+        assert_eq!(overridden, vec!["time"]);
+    }
+
+    #[test]
+    fn missing_past_events_kept() {
+        let existing = Events {
+            events: vec![event("a", 1, "Gent"), event("b", 2, "Gent")],
+        };
+        let sync = sync_events(
+            &existing,
+            None,
+            &Events::default(),
+            source_id,
+            MissingPolicy::Remove,
+            &DateWindow {
+                start: Some(NaiveDate::from_ymd(2022, 3, 2)),
+                end: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(sync.events, vec![event("a", 1, "Gent")]);
+        assert_eq!(sync.missing, 1);
+    }
+
+    #[test]
+    fn refetched_source_unchanged() {
+        let with_source = |name, day| {
+            let mut event = event(name, 1, "Gent");
+            event.source = Some(Source {
+                importer: "balbende".to_string(),
+                id: "42".to_string(),
+                fetched: Some(Utc.ymd(2022, 2, day).and_hms(12, 0, 0)),
+                licence: None,
+                attribution: None,
+            });
+            event
+        };
+        let existing = Events {
+            events: vec![with_source("a", 1)],
+        };
+        let sync = sync_events(
+            &existing,
+            Some(&existing),
+            &Events {
+                events: vec![with_source("a", 2)],
+            },
+            |event| event.source.as_ref().map(|source| source.id.clone()),
+            MissingPolicy::Cancel,
+            &DateWindow::default(),
+        )
+        .unwrap();
+        assert_eq!(sync.events, vec![with_source("a", 1)]);
+        assert_eq!((sync.added, sync.updated, sync.unchanged), (0, 0, 1));
+    }
+
+    #[test]
+    fn reappearing_event_uncancelled() {
+        let window = DateWindow {
+            start: Some(NaiveDate::from_ymd(2022, 3, 1)),
+            end: None,
+        };
+        let events = Events {
+            events: vec![event("a", 1, "Gent"), event("b", 2, "Gent")],
+        };
+        let without_b = Events {
+            events: vec![event("a", 1, "Gent")],
+        };
+        let first = sync_events(
+            &events,
+            Some(&events),
+            &without_b,
+            source_id,
+            MissingPolicy::Cancel,
+            &window,
+        )
+        .unwrap();
+        let mut cancelled_b = event("b", 2, "Gent");
+        cancelled_b.cancelled = true;
+        assert_eq!(
+            first.events,
+            vec![event("a", 1, "Gent"), cancelled_b.clone()]
+        );
+        assert_eq!(first.base, vec![cancelled_b, event("a", 1, "Gent")]);
+
+        let second = sync_events(
+            &Events {
+                events: first.events,
+            },
+            Some(&Events { events: first.base }),
+            &events,
+            source_id,
+            MissingPolicy::Cancel,
+            &window,
+        )
+        .unwrap();
+        assert_eq!(second.events, events.events);
+        assert_eq!(second.updated, 1);
+    }
+
+    #[test]
+    fn no_base_reported() {
+        let existing = Events {
+            events: vec![event("a", 1, "Gent"), event("b", 2, "Gent")],
+        };
+        let imported = Events {
+            events: vec![event("a", 1, "Gent"), event("b", 3, "Gent")],
+        };
+        let sync = sync_events(
+            &existing,
+            None,
+            &imported,
+            source_id,
+            MissingPolicy::Cancel,
+            &DateWindow::default(),
+        )
+        .unwrap();
+        assert_eq!(sync.events, existing.events);
+        assert_eq!(sync.without_base, vec!["b"]);
+    }
+}
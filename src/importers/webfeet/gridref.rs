@@ -0,0 +1,247 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// The conversions follow the formulae in "A Guide to Coordinate Systems in Great Britain" from
+// Ordnance Survey.
+
+use std::f64::consts::PI;
+
+/// The semi-major and semi-minor axes of the Airy 1830 ellipsoid used by OSGB36, in metres.
+const AIRY_A: f64 = 6_377_563.396;
+const AIRY_B: f64 = 6_356_256.909;
+/// The semi-major and semi-minor axes of the GRS80 ellipsoid used by WGS84, in metres.
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_B: f64 = 6_356_752.314_245;
+
+/// The scale factor on the central meridian of the National Grid.
+const F0: f64 = 0.999_601_271_7;
+/// The true origin of the National Grid, in degrees.
+const LATITUDE_0: f64 = 49.0;
+const LONGITUDE_0: f64 = -2.0;
+/// The easting and northing of the true origin, in metres.
+const E0: f64 = 400_000.0;
+const N0: f64 = -100_000.0;
+
+/// The Helmert transformation from OSGB36 to WGS84: translations in metres, scale in parts per
+/// million and rotations in arcseconds.
+const TX: f64 = 446.448;
+const TY: f64 = -125.157;
+const TZ: f64 = 542.060;
+const SCALE_PPM: f64 = -20.4894;
+const RX: f64 = 0.1502;
+const RY: f64 = 0.2470;
+const RZ: f64 = 0.8421;
+
+/// Converts a grid reference such as "SP513062" or "TQ 38886 77332" to the WGS84 latitude and
+/// longitude of the centre of the square it refers to, in degrees. Returns `None` if it isn't a
+/// valid grid reference.
+pub fn grid_reference_to_wgs84(reference: &str) -> Option<(f64, f64)> {
+    let (easting, northing) = parse_grid_reference(reference)?;
+    let (latitude, longitude) = grid_to_osgb36(easting, northing);
+    Some(osgb36_to_wgs84(latitude, longitude))
+}
+
+/// Parses a grid reference to the easting and northing of the centre of its square, in metres.
+fn parse_grid_reference(reference: &str) -> Option<(f64, f64)> {
+    let reference: String = reference
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    let mut chars = reference.chars();
+    let first = letter_index(chars.next()?)?;
+    let second = letter_index(chars.next()?)?;
+    let digits = chars.as_str();
+    if !digits.len().is_multiple_of(2)
+        || digits.len() > 10
+        || !digits.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+
+    // The first letter gives the 500 km square and the second the 100 km square within it, from
+    // a 5x5 grid of letters skipping I.
+    let easting_100km = ((first - 2).rem_euclid(5)) * 5 + second % 5;
+    let northing_100km = (19 - (first / 5) * 5) - second / 5;
+    if !(0..7).contains(&easting_100km) || !(0..13).contains(&northing_100km) {
+        return None;
+    }
+
+    let precision = digits.len() / 2;
+    let square_size = 10f64.powi(5 - precision as i32);
+    let (easting, northing) = digits.split_at(precision);
+    let parse = |digits: &str| -> f64 {
+        if digits.is_empty() {
+            0.0
+        } else {
+            digits.parse::<f64>().unwrap() * square_size
+        }
+    };
+    Some((
+        easting_100km as f64 * 100_000.0 + parse(easting) + square_size / 2.0,
+        northing_100km as f64 * 100_000.0 + parse(northing) + square_size / 2.0,
+    ))
+}
+
+/// Gets the index of a grid letter, skipping I.
+fn letter_index(letter: char) -> Option<i32> {
+    if !letter.is_ascii_uppercase() || letter == 'I' {
+        return None;
+    }
+    let index = letter as i32 - 'A' as i32;
+    Some(if index > 7 { index - 1 } else { index })
+}
+
+/// Converts an easting and northing to OSGB36 latitude and longitude in degrees, by the inverse
+/// transverse Mercator projection.
+fn grid_to_osgb36(easting: f64, northing: f64) -> (f64, f64) {
+    let (a, b) = (AIRY_A, AIRY_B);
+    let e2 = 1.0 - (b * b) / (a * a);
+    let n = (a - b) / (a + b);
+    let (n2, n3) = (n * n, n * n * n);
+    let phi0 = LATITUDE_0.to_radians();
+    let lambda0 = LONGITUDE_0.to_radians();
+
+    // Find the latitude for which the meridional arc matches the northing.
+    let mut phi = phi0;
+    let mut m = 0.0;
+    loop {
+        phi += (northing - N0 - m) / (a * F0);
+        let (d, s) = (phi - phi0, phi + phi0);
+        m = b
+            * F0
+            * ((1.0 + n + 1.25 * n2 + 1.25 * n3) * d
+                - (3.0 * n + 3.0 * n2 + 21.0 / 8.0 * n3) * d.sin() * s.cos()
+                + (15.0 / 8.0 * n2 + 15.0 / 8.0 * n3) * (2.0 * d).sin() * (2.0 * s).cos()
+                - 35.0 / 24.0 * n3 * (3.0 * d).sin() * (3.0 * s).cos());
+        if (northing - N0 - m).abs() < 0.000_01 {
+            break;
+        }
+    }
+
+    let sin_phi = phi.sin();
+    let nu = a * F0 / (1.0 - e2 * sin_phi * sin_phi).sqrt();
+    let rho = a * F0 * (1.0 - e2) / (1.0 - e2 * sin_phi * sin_phi).powf(1.5);
+    let eta2 = nu / rho - 1.0;
+    let tan = phi.tan();
+    let (tan2, tan4, tan6) = (tan * tan, tan.powi(4), tan.powi(6));
+    let sec = 1.0 / phi.cos();
+
+    let vii = tan / (2.0 * rho * nu);
+    let viii = tan / (24.0 * rho * nu.powi(3)) * (5.0 + 3.0 * tan2 + eta2 - 9.0 * tan2 * eta2);
+    let ix = tan / (720.0 * rho * nu.powi(5)) * (61.0 + 90.0 * tan2 + 45.0 * tan4);
+    let x = sec / nu;
+    let xi = sec / (6.0 * nu.powi(3)) * (nu / rho + 2.0 * tan2);
+    let xii = sec / (120.0 * nu.powi(5)) * (5.0 + 28.0 * tan2 + 24.0 * tan4);
+    let xiia = sec / (5040.0 * nu.powi(7)) * (61.0 + 662.0 * tan2 + 1320.0 * tan4 + 720.0 * tan6);
+
+    let de = easting - E0;
+    let latitude = phi - vii * de.powi(2) + viii * de.powi(4) - ix * de.powi(6);
+    let longitude = lambda0 + x * de - xi * de.powi(3) + xii * de.powi(5) - xiia * de.powi(7);
+    (latitude.to_degrees(), longitude.to_degrees())
+}
+
+/// Converts OSGB36 latitude and longitude to WGS84 by a Helmert transformation, in degrees.
+fn osgb36_to_wgs84(latitude: f64, longitude: f64) -> (f64, f64) {
+    let (x, y, z) = to_cartesian(latitude, longitude, AIRY_A, AIRY_B);
+
+    let arcseconds = |value: f64| value / 3600.0 * PI / 180.0;
+    let (rx, ry, rz) = (arcseconds(RX), arcseconds(RY), arcseconds(RZ));
+    let scale = 1.0 + SCALE_PPM / 1_000_000.0;
+    let x2 = TX + scale * x - rz * y + ry * z;
+    let y2 = TY + rz * x + scale * y - rx * z;
+    let z2 = TZ - ry * x + rx * y + scale * z;
+
+    from_cartesian(x2, y2, z2, WGS84_A, WGS84_B)
+}
+
+/// Converts latitude and longitude in degrees on the given ellipsoid, at zero height, to cartesian
+/// coordinates.
+fn to_cartesian(latitude: f64, longitude: f64, a: f64, b: f64) -> (f64, f64, f64) {
+    let (phi, lambda) = (latitude.to_radians(), longitude.to_radians());
+    let e2 = 1.0 - (b * b) / (a * a);
+    let nu = a / (1.0 - e2 * phi.sin().powi(2)).sqrt();
+    (
+        nu * phi.cos() * lambda.cos(),
+        nu * phi.cos() * lambda.sin(),
+        (1.0 - e2) * nu * phi.sin(),
+    )
+}
+
+/// Converts cartesian coordinates to latitude and longitude in degrees on the given ellipsoid.
+fn from_cartesian(x: f64, y: f64, z: f64, a: f64, b: f64) -> (f64, f64) {
+    let e2 = 1.0 - (b * b) / (a * a);
+    let p = (x * x + y * y).sqrt();
+    let mut phi = z.atan2(p * (1.0 - e2));
+    loop {
+        let nu = a / (1.0 - e2 * phi.sin().powi(2)).sqrt();
+        let next = (z + e2 * nu * phi.sin()).atan2(p);
+        if (next - phi).abs() < 1e-12 {
+            phi = next;
+            break;
+        }
+        phi = next;
+    }
+    (phi.to_degrees(), y.atan2(x).to_degrees())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: (f64, f64), expected: (f64, f64), tolerance: f64) {
+        assert!(
+            (actual.0 - expected.0).abs() < tolerance && (actual.1 - expected.1).abs() < tolerance,
+            "{:?} is not close to {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_references() {
+        assert_eq!(
+            parse_grid_reference("TG 51409 13177"),
+            Some((651_409.5, 313_177.5))
+        );
+        assert_eq!(
+            parse_grid_reference("SP513062"),
+            Some((451_350.0, 206_250.0))
+        );
+        assert_eq!(parse_grid_reference("NN"), Some((250_000.0, 750_000.0)));
+        assert_eq!(parse_grid_reference("SP51306"), None);
+        assert_eq!(parse_grid_reference("IP513062"), None);
+        assert_eq!(parse_grid_reference("Oxford"), None);
+    }
+
+    #[test]
+    fn ordnance_survey_example() {
+        // The worked example from the Ordnance Survey guide.
+        assert_close(
+            grid_to_osgb36(651_409.903, 313_177.270),
+            (52.657_570_3, 1.717_921_6),
+            0.000_000_1,
+        );
+    }
+
+    #[test]
+    fn convert_to_wgs84() {
+        // Central Oxford.
+        assert_close(
+            grid_reference_to_wgs84("SP513062").unwrap(),
+            (51.752, -1.258),
+            0.001,
+        );
+    }
+}
@@ -12,216 +12,440 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod gridref;
 pub mod types;
 
-use self::types::{EventRecord, Eventlist, Style};
-use crate::model::{
-    dancestyle::DanceStyle,
-    event::{Event, EventTime},
-    events::Events,
+use self::{
+    gridref::grid_reference_to_wgs84,
+    types::{EventRecord, Eventlist},
+};
+use super::{location::parse_location, DateWindow, ImportWarning, Imported, Importer, WarningKind};
+use crate::{
+    config::SourceConfig,
+    model::{
+        dancestyle::DanceStyle,
+        event::{Event, EventTime, Venue},
+        events::Events,
+    },
 };
 use chrono::NaiveDate;
-use eyre::Report;
-
-pub async fn events() -> Result<Vec<EventRecord>, Report> {
-    let xml = reqwest::get("https://www.webfeet.org/dance.xml")
-        .await?
-        .text()
-        .await?;
-    let xml = replace_entities(&xml);
-    let event_list: Eventlist = quick_xml::de::from_str(&xml)?;
-    let mut events = event_list.event_record;
-    // Sort by ID to give a stable order.
-    events.sort_by(|a, b| a.id.value.cmp(&b.id.value));
-    for event in &events {
-        if event.canonical_date.isoformat.contains("-") {
-            eprintln!("{}", event.canonical_date.isoformat);
-        }
-        if let Some(text_date) = &event.text_date {
-            if event.canonical_date.isoformat != text_date.isoformat {
-                eprintln!(
-                    "{} != {}",
-                    event.canonical_date.isoformat, text_date.isoformat
-                );
-            }
-        }
+use eyre::{bail, eyre, Report};
+use std::collections::HashMap;
+
+/// The name of the webfeet importer, and of its source in the config file.
+pub const NAME: &str = "webfeet";
+
+/// The URL of the full list of events on webfeet.
+const DEFAULT_URL: &str = "https://www.webfeet.org/dance.xml";
+
+/// The dance styles for webfeet style names, and for caller names which webfeet uses to give the
+/// style instead of a caller.
+const DEFAULT_STYLES: &[(&str, &[DanceStyle])] = &[
+    ("Contra", &[DanceStyle::Contra]),
+    ("Dance.American.American Contra", &[DanceStyle::Contra]),
+    ("Dance.Contra", &[DanceStyle::Contra]),
+    ("Dance.Eurobal", &[DanceStyle::Balfolk]),
+    ("Dance.European", &[DanceStyle::Balfolk]),
+    ("Dance.French/Breton", &[DanceStyle::Balfolk]),
+    ("Dance.Country Dance", &[DanceStyle::Playford]),
+    ("Dance.Ceilidh", &[DanceStyle::EnglishCeilidh]),
+    ("Dance.English Ceilidh", &[DanceStyle::EnglishCeilidh]),
+    ("Dance.English Folk", &[DanceStyle::EnglishCeilidh]),
+    ("Ceilidh", &[DanceStyle::EnglishCeilidh]),
+    ("Barn Dance", &[DanceStyle::EnglishCeilidh]),
+];
+
+/// Imports folk dance events in the UK from webfeet.org.
+pub struct Webfeet {
+    /// The dance styles for each webfeet style name, keyed by the lowercase name.
+    styles: HashMap<String, Vec<DanceStyle>>,
+}
+
+impl Webfeet {
+    /// Creates a webfeet importer with the built-in style mapping, extended or overridden by the
+    /// mapping in the given config.
+    pub fn new(config: &SourceConfig) -> Self {
+        let styles = DEFAULT_STYLES
+            .iter()
+            .map(|(name, styles)| (name.to_lowercase(), styles.to_vec()))
+            .chain(
+                config
+                    .style_mapping
+                    .iter()
+                    .map(|(name, styles)| (name.to_lowercase(), styles.clone())),
+            )
+            .collect();
+        Self { styles }
+    }
+
+    /// Gets the dance styles for the given webfeet style name, or `None` if it isn't known.
+    fn map_style(&self, style: &str) -> Option<&[DanceStyle]> {
+        self.styles.get(&style.to_lowercase()).map(Vec::as_slice)
     }
-    Ok(events)
 }
 
-pub async fn import_events() -> Result<Events, Report> {
-    let event_records = events().await?;
+impl Importer for Webfeet {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn description(&self) -> &str {
+        "Folk dance events in the UK from webfeet.org"
+    }
+
+    /// Webfeet only provides a single list of all events, so the date window is applied after
+    /// fetching it.
+    fn url(&self, config: &SourceConfig, _window: &DateWindow) -> Result<String, Report> {
+        Ok(config.url.as_deref().unwrap_or(DEFAULT_URL).to_owned())
+    }
+
+    fn convert(&self, raw: &str) -> Result<Imported, Report> {
+        let mut warnings = vec![];
+        let event_records = parse_events(raw)?;
 
-    let mut events = vec![];
-    let mut merging_event: Option<Event> = None;
-    for event in &event_records {
-        if let Some(converted) = convert(event) {
-            if let Some(previous_event) = merging_event {
-                if let Some(merged) = previous_event.merge(&converted) {
-                    merging_event = Some(merged);
+        let mut events = vec![];
+        let mut merging_event: Option<Event> = None;
+        for event in &event_records {
+            if let Some(converted) = self.convert_record(event, &mut warnings) {
+                if let Some(previous_event) = merging_event {
+                    if let Some(merged) = previous_event.merge(&converted) {
+                        merging_event = Some(merged);
+                    } else {
+                        events.push(previous_event);
+                        merging_event = Some(converted);
+                    }
                 } else {
-                    events.push(previous_event);
                     merging_event = Some(converted);
                 }
-            } else {
-                merging_event = Some(converted);
             }
         }
+        events.extend(merging_event);
+
+        Ok(Imported {
+            events: Events { events },
+            warnings,
+        })
     }
-    events.extend(merging_event);
 
-    Ok(Events { events })
-}
+    /// The ID is based on the reference URL of the first record, which is always the first link,
+    /// and the date. Events which weren't imported from webfeet have no ID.
+    fn source_id(&self, event: &Event) -> Option<String> {
+        if event.organisation.as_deref() != Some("Webfeet") {
+            return None;
+        }
+        let link = event.links.first()?;
+        Some(format!("{} {}", link, event.start_date()))
+    }
 
-fn replace_entities(source: &str) -> String {
-    source
-        .replace("&icirc;", "&#238;")
-        .replace("&Ouml;", "&#214;")
+    fn attribution(&self) -> Option<&str> {
+        Some("Event data from webfeet.org")
+    }
+
+    fn default_rules(&self) -> &str {
+        include_str!("rules.toml")
+    }
 }
 
-fn convert(event: &EventRecord) -> Option<Event> {
-    let mut details = None;
-    let bands: Vec<String> = event
-        .band_collection
-        .band
-        .iter()
-        .map(|band| band.value.clone())
-        .collect();
-    let city = event.location_collection.location.value.clone();
-
-    let mut name = format!("{} in {}", bands.join(" & "), city);
-    let mut cancelled = false;
-    if let Some(event) = event.event_collection.event.first() {
-        if event.value.chars().next().unwrap() == '[' {
-            if event.value == "[Cancelled]" || event.value == "[Postponed]" {
-                cancelled = true;
+impl Webfeet {
+    /// Converts a webfeet record to an event, or returns `None` and adds a warning if it can't be
+    /// converted. Records whose date is uncertain, or whose text date doesn't match the canonical
+    /// date, are imported as tentative.
+    fn convert_record(
+        &self,
+        event: &EventRecord,
+        warnings: &mut Vec<ImportWarning>,
+    ) -> Option<Event> {
+        let mut details = None;
+        let bands: Vec<String> = event
+            .band_collection
+            .band
+            .iter()
+            .map(|band| band.value.clone())
+            .collect();
+        let location = &event.location_collection.location;
+        let parsed_location = parse_location(&location.value);
+        let city = parsed_location
+            .city
+            .unwrap_or_else(|| location.value.trim().to_owned());
+        let mut venue = parsed_location.venue;
+        if let Some((latitude, longitude)) =
+            location.mapref.as_deref().and_then(grid_reference_to_wgs84)
+        {
+            // Six decimal places is well within the precision of any grid reference.
+            venue.latitude = Some((latitude * 1e6).round() / 1e6);
+            venue.longitude = Some((longitude * 1e6).round() / 1e6);
+        }
+
+        let mut name = format!("{} in {}", bands.join(" & "), city);
+        let mut cancelled = false;
+        if let Some(event) = event.event_collection.event.first() {
+            if event.value.starts_with('[') {
+                if event.value == "[Cancelled]" || event.value == "[Postponed]" {
+                    cancelled = true;
+                }
+                details = Some(event.value.clone());
+            } else {
+                name = event.value.clone();
             }
-            details = Some(event.value.clone());
-        } else {
-            name = event.value.clone();
         }
-    }
 
-    let mut callers = vec![];
-    let mut styles = vec![];
-    let mut links = vec![event.reference.url.clone()];
-    for event in &event.event_collection.event {
-        if let Some(style) = event.style {
-            styles.extend(convert_style(style));
+        let mut callers = vec![];
+        let mut styles = vec![];
+        let mut unmapped_styles = vec![];
+        let mut links = vec![event.reference.url.clone()];
+        let style_names = event
+            .event_collection
+            .event
+            .iter()
+            .filter_map(|event| event.style.as_deref())
+            .chain(
+                event
+                    .band_collection
+                    .band
+                    .iter()
+                    .filter_map(|band| band.style.as_deref()),
+            )
+            .chain(
+                event
+                    .caller_collection
+                    .caller
+                    .iter()
+                    .filter_map(|caller| caller.style.as_deref()),
+            );
+        for style in style_names {
+            if let Some(mapped) = self.map_style(style) {
+                styles.extend_from_slice(mapped);
+            } else if !unmapped_styles.contains(&style) {
+                unmapped_styles.push(style);
+            }
         }
-    }
-    for band in &event.band_collection.band {
-        if let Some(style) = band.style {
-            styles.extend(convert_style(style));
+        for caller in &event.caller_collection.caller {
+            // Webfeet sometimes gives the style of dance in place of a caller.
+            if let Some(mapped) = self.map_style(&caller.value) {
+                styles.extend_from_slice(mapped);
+            } else if caller.value.starts_with("http") {
+                links.push(caller.value.clone());
+            } else {
+                callers.push(caller.value.clone());
+            }
         }
-    }
-    for caller in &event.caller_collection.caller {
-        if let Some(style) = caller.style {
-            styles.extend(convert_style(style));
+        styles.sort();
+        styles.dedup();
+
+        let description = format!("{} {}", event.canonical_date.isoformat, name);
+        let warning = |kind, reason: &str| {
+            ImportWarning::new(kind, &description, Some(reason)).with_id(&event.id.value)
+        };
+        let time = match parse_date(&event.canonical_date.isoformat) {
+            Ok(time) => time,
+            Err(e) => {
+                warnings.push(warning(WarningKind::Dropped, &e.to_string()));
+                return None;
+            }
+        };
+        let mut tentative = event.canonical_date.uncertainty.unwrap_or_default() > 0;
+        if let Some(text_date) = &event.text_date {
+            if text_date.isoformat != event.canonical_date.isoformat {
+                warnings.push(warning(
+                    WarningKind::Inconsistent,
+                    &format!("text date {}", text_date.isoformat),
+                ));
+                tentative = true;
+            }
         }
-        let value_lowercase = caller.value.to_lowercase();
-        if value_lowercase == "ceilidh" {
-            styles.push(DanceStyle::EnglishCeilidh);
-        } else if value_lowercase == "barn dance" {
-        } else if caller.value.starts_with("http") {
-            links.push(caller.value.clone());
-        } else {
-            callers.push(caller.value.clone());
+
+        if styles.is_empty() {
+            let reason = if unmapped_styles.is_empty() {
+                "no styles".to_string()
+            } else {
+                format!("no known styles in {}", unmapped_styles.join(", "))
+            };
+            warnings.push(warning(WarningKind::Dropped, &reason));
+            return None;
         }
-    }
-    styles.sort();
-    styles.dedup();
-
-    if styles.is_empty() {
-        eprintln!("Dropping {} with no styles.", name);
-        None
-    } else if city == "Zoom" {
-        eprintln!("Dropping {} on Zoom.", name);
-        None
-    } else if city == "Cecil Sharp House, Camden" {
-        eprintln!("Dropping {} at Cecil Sharp House.", name);
-        None
-    } else {
+
         Some(Event {
+            id: None,
             name,
             details,
             links,
-            time: parse_date(&event.canonical_date.isoformat),
-            country: "UK".to_string(),
+            time,
+            timezone: None,
+            country: parsed_location.country.unwrap_or_else(|| "UK".to_string()),
             city,
+            region: location.area.clone(),
             styles,
             workshop: false,
             social: true,
             bands,
             callers,
+            teachers: vec![],
             price: None,
             organisation: Some("Webfeet".to_string()),
             cancelled,
+            tentative,
+            venue: if venue == Venue::default() {
+                None
+            } else {
+                Some(venue)
+            },
+            schedule: vec![],
+            band_details: vec![],
+            recurrence: None,
+            source: None,
         })
     }
 }
 
-fn parse_date(date_str: &str) -> EventTime {
-    let start_date = NaiveDate::parse_from_str(&date_str[0..8], "%Y%m%d").unwrap();
-    let end_date = if date_str.len() > 8 {
-        let end_date_string = format!("{}{}", &date_str[0..17 - date_str.len()], &date_str[9..]);
-        NaiveDate::parse_from_str(&end_date_string, "%Y%m%d").unwrap()
-    } else {
-        start_date
-    };
-    EventTime::DateOnly {
-        start_date,
-        end_date,
-    }
+/// Parses the XML event list from webfeet, adding warnings for any records with inconsistent dates.
+fn parse_events(xml: &str) -> Result<Vec<EventRecord>, Report> {
+    let xml = replace_entities(xml);
+    let event_list: Eventlist = quick_xml::de::from_str(&xml)?;
+    let mut events = event_list.event_record;
+    // Sort by ID to give a stable order.
+    events.sort_by(|a, b| a.id.value.cmp(&b.id.value));
+    Ok(events)
 }
 
-fn convert_style(style: Style) -> Option<DanceStyle> {
-    match style {
-        Style::Contra | Style::DanceContra | Style::DanceAmericanAmericanContra => {
-            Some(DanceStyle::Contra)
-        }
-        Style::DanceEurobal | Style::DanceEuropean | Style::DanceFrenchBreton => {
-            Some(DanceStyle::Balfolk)
+fn replace_entities(source: &str) -> String {
+    source
+        .replace("&icirc;", "&#238;")
+        .replace("&Ouml;", "&#214;")
+}
+
+/// Parses a webfeet date, which is either a single date such as "20210114" or a range. The end of
+/// a range only gives the parts which differ from the start, e.g. "20210114-16",
+/// "20210114-0203" or "20210114-20220607".
+fn parse_date(date: &str) -> Result<EventTime, Report> {
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    let (start, end) = match date.split_once('-') {
+        Some((start, end)) => (start, Some(end)),
+        None => (date, None),
+    };
+    if start.len() != 8 || !is_digits(start) {
+        bail!("invalid date {}", date);
+    }
+    let start_date =
+        NaiveDate::parse_from_str(start, "%Y%m%d").map_err(|_| eyre!("invalid date {}", date))?;
+    let end_date = match end {
+        None => start_date,
+        Some(end) if matches!(end.len(), 2 | 4 | 8) && is_digits(end) => {
+            let end = format!("{}{}", &start[..8 - end.len()], end);
+            NaiveDate::parse_from_str(&end, "%Y%m%d")
+                .map_err(|_| eyre!("invalid end date {}", date))?
         }
-        Style::DanceCountryDance => Some(DanceStyle::Playford),
-        Style::DanceEnglishCeilidh | Style::DanceCeilidh => Some(DanceStyle::EnglishCeilidh),
-        Style::DanceEnglishFolk => None, // TODO
+        Some(_) => bail!("invalid end date {}", date),
+    };
+    if end_date < start_date {
+        bail!("date range {} ends before it starts", date);
     }
+    Ok(EventTime::DateOnly {
+        start_date,
+        end_date,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{format::events_yaml, importers::rules::Rules};
+
+    #[test]
+    fn convert_fixture() {
+        let webfeet = Webfeet::new(&SourceConfig::default());
+        let mut imported = webfeet.convert(include_str!("testdata/dance.xml")).unwrap();
+        Rules::parse(webfeet.default_rules())
+            .unwrap()
+            .apply(&mut imported);
+        assert_eq!(
+            events_yaml(&imported.events).unwrap(),
+            include_str!("testdata/dance.yaml")
+        );
+        assert_eq!(
+            imported
+                .warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "Inconsistent: [3003] 20300220 Folk Band in Bristol (text date 20300221)",
+                "Dropped: [3006] 2030041 Broken Band in Leeds (invalid date 2030041)",
+                "Dropped: [3008] 20300517 Side in Exeter (no known styles in Dance.Morris)",
+                "Dropped: 2030-03-01 Bal Band in Zoom (on Zoom)",
+            ]
+        );
+    }
+
+    #[test]
+    fn configured_style_mapping() {
+        let config = SourceConfig {
+            style_mapping: [
+                ("dance.morris".to_string(), vec![DanceStyle::EnglishCeilidh]),
+                ("Dance.Contra".to_string(), vec![]),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let webfeet = Webfeet::new(&config);
+        assert_eq!(
+            webfeet.map_style("Dance.Morris"),
+            Some(&[DanceStyle::EnglishCeilidh][..])
+        );
+        assert_eq!(webfeet.map_style("DANCE.CONTRA"), Some(&[][..]));
+        assert_eq!(
+            webfeet.map_style("Dance.Eurobal"),
+            Some(&[DanceStyle::Balfolk][..])
+        );
+        assert_eq!(webfeet.map_style("Dance.Rapper"), None);
+    }
 
     #[test]
     fn parse_dates() {
         assert_eq!(
-            parse_date("20210114"),
+            parse_date("20210114").unwrap(),
             EventTime::DateOnly {
                 start_date: NaiveDate::from_ymd(2021, 1, 14),
                 end_date: NaiveDate::from_ymd(2021, 1, 14),
             }
         );
         assert_eq!(
-            parse_date("20210114-16"),
+            parse_date("20210114-16").unwrap(),
             EventTime::DateOnly {
                 start_date: NaiveDate::from_ymd(2021, 1, 14),
                 end_date: NaiveDate::from_ymd(2021, 1, 16),
             }
         );
         assert_eq!(
-            parse_date("20210114-0203"),
+            parse_date("20210114-0203").unwrap(),
             EventTime::DateOnly {
                 start_date: NaiveDate::from_ymd(2021, 1, 14),
                 end_date: NaiveDate::from_ymd(2021, 2, 3),
             }
         );
         assert_eq!(
-            parse_date("20210114-20220607"),
+            parse_date("20210114-20220607").unwrap(),
             EventTime::DateOnly {
                 start_date: NaiveDate::from_ymd(2021, 1, 14),
                 end_date: NaiveDate::from_ymd(2022, 6, 7),
             }
         );
     }
+
+    #[test]
+    fn invalid_dates() {
+        for (date, error) in [
+            ("", "invalid date "),
+            ("2021-01-14", "invalid date 2021-01-14"),
+            ("20210231", "invalid date 20210231"),
+            ("20210114-1", "invalid end date 20210114-1"),
+            ("20210114-32", "invalid end date 20210114-32"),
+            (
+                "20210114-12",
+                "date range 20210114-12 ends before it starts",
+            ),
+        ] {
+            assert_eq!(parse_date(date).unwrap_err().to_string(), error);
+        }
+    }
 }
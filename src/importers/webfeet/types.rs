@@ -72,7 +72,7 @@ pub struct EventCollection {
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(deny_unknown_fields, rename_all = "PascalCase")]
 pub struct Event {
-    pub style: Option<Style>,
+    pub style: Option<String>,
     #[serde(rename = "$value")]
     pub value: String,
 }
@@ -88,7 +88,7 @@ pub struct BandCollection {
 pub struct Band {
     #[serde(default)]
     pub status: Status,
-    pub style: Option<Style>,
+    pub style: Option<String>,
     #[serde(rename = "$value")]
     pub value: String,
 }
@@ -104,7 +104,7 @@ pub struct CallerCollection {
 pub struct Caller {
     #[serde(default)]
     pub status: Status,
-    pub style: Option<Style>,
+    pub style: Option<String>,
     #[serde(rename = "$value")]
     pub value: String,
 }
@@ -125,41 +125,13 @@ pub struct Location {
     pub value: String,
 }
 
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub enum Status {
+    #[default]
     Unrecognised,
     Recognised,
 }
 
-impl Default for Status {
-    fn default() -> Self {
-        Self::Unrecognised
-    }
-}
-
-#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
-pub enum Style {
-    Contra,
-    #[serde(rename = "Dance.American.American Contra")]
-    DanceAmericanAmericanContra,
-    #[serde(rename = "Dance.Eurobal")]
-    DanceEurobal,
-    #[serde(rename = "Dance.European")]
-    DanceEuropean,
-    #[serde(rename = "Dance.French/Breton")]
-    DanceFrenchBreton,
-    #[serde(rename = "Dance.Contra")]
-    DanceContra,
-    #[serde(rename = "Dance.Country Dance")]
-    DanceCountryDance,
-    #[serde(rename = "Dance.Ceilidh")]
-    DanceCeilidh,
-    #[serde(rename = "Dance.English Ceilidh")]
-    DanceEnglishCeilidh,
-    #[serde(rename = "Dance.English Folk")]
-    DanceEnglishFolk,
-}
-
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(deny_unknown_fields, rename_all = "PascalCase")]
 pub struct Reference {
@@ -183,7 +155,8 @@ pub enum SourceFormat {
     Custom,
     #[serde(rename = "JSON-LD")]
     JsonLd,
-    PBR,
+    #[serde(rename = "PBR")]
+    Pbr,
     Table,
     UL,
 }
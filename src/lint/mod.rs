@@ -0,0 +1,101 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod rules;
+
+pub use self::rules::COUNTRIES;
+
+use crate::{
+    config::LintConfig,
+    model::event::Event,
+    validate::{LocatedEvent, Problem, Severity},
+};
+use eyre::{bail, Report};
+
+/// A check for likely mistakes or inconsistencies in event data, which doesn't stop the events from
+/// being loaded.
+pub trait Rule {
+    /// The name by which the rule can be enabled or disabled in the config file.
+    fn name(&self) -> &'static str;
+
+    /// Checks a single event, returning a message for each problem found.
+    fn check_event(&self, _event: &Event) -> Vec<String> {
+        vec![]
+    }
+
+    /// Checks for problems between events, returning the index of the event and a message for each
+    /// problem found.
+    fn check_all(&self, _events: &[&Event]) -> Vec<(usize, String)> {
+        vec![]
+    }
+}
+
+/// Gets all available lint rules.
+pub fn all_rules() -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(rules::LinkFormat),
+        Box::new(rules::MissingLinks),
+        Box::new(rules::LongDuration),
+        Box::new(rules::FarFuture),
+        Box::new(rules::UncalledCallers),
+        Box::new(rules::UnknownCountry),
+        Box::new(rules::NameConsistency),
+    ]
+}
+
+/// Gets the lint rules which are enabled by the given config. Rules are enabled unless the config
+/// disables them.
+pub fn enabled_rules(config: &LintConfig) -> Result<Vec<Box<dyn Rule>>, Report> {
+    let rules = all_rules();
+    for name in config.rules.keys() {
+        if !rules.iter().any(|rule| rule.name() == name) {
+            bail!("Unknown lint rule '{}'", name);
+        }
+    }
+    Ok(rules
+        .into_iter()
+        .filter(|rule| config.rules.get(rule.name()).copied().unwrap_or(true))
+        .collect())
+}
+
+/// Runs the given rules over the given events, returning a warning for each problem found, in
+/// order of position.
+pub fn lint(rules: &[Box<dyn Rule>], events: &[LocatedEvent]) -> Vec<Problem> {
+    let event_refs: Vec<&Event> = events.iter().map(|located| &located.event).collect();
+    let mut problems = vec![];
+    for rule in rules {
+        for located in events {
+            problems.extend(
+                rule.check_event(&located.event)
+                    .into_iter()
+                    .map(|message| warning(rule.as_ref(), located, message)),
+            );
+        }
+        problems.extend(
+            rule.check_all(&event_refs)
+                .into_iter()
+                .map(|(index, message)| warning(rule.as_ref(), &events[index], message)),
+        );
+    }
+    problems.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    problems
+}
+
+fn warning(rule: &dyn Rule, event: &LocatedEvent, message: String) -> Problem {
+    Problem::for_event(
+        Severity::Warning,
+        event,
+        format!("{} [{}]", message, rule.name()),
+    )
+}
@@ -0,0 +1,505 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::Rule;
+use crate::model::event::Event;
+use chrono::{Duration, Utc};
+use reqwest::Url;
+use std::collections::HashMap;
+
+/// Events lasting longer than this many days are probably a mistake.
+const MAX_DURATION_DAYS: i64 = 14;
+
+/// Events starting more than this many days in the future probably have the wrong year.
+const MAX_DAYS_AHEAD: i64 = 2 * 365;
+
+/// Country names as used in events files.
+pub const COUNTRIES: &[&str] = &[
+    "Afghanistan",
+    "Albania",
+    "Algeria",
+    "Andorra",
+    "Angola",
+    "Antigua and Barbuda",
+    "Argentina",
+    "Armenia",
+    "Australia",
+    "Austria",
+    "Azerbaijan",
+    "Bahamas",
+    "Bahrain",
+    "Bangladesh",
+    "Barbados",
+    "Belarus",
+    "Belgium",
+    "Belize",
+    "Benin",
+    "Bhutan",
+    "Bolivia",
+    "Bosnia and Herzegovina",
+    "Botswana",
+    "Brazil",
+    "Brunei",
+    "Bulgaria",
+    "Burkina Faso",
+    "Burundi",
+    "Cambodia",
+    "Cameroon",
+    "Canada",
+    "Cape Verde",
+    "Central African Republic",
+    "Chad",
+    "Chile",
+    "China",
+    "Colombia",
+    "Comoros",
+    "Congo",
+    "Costa Rica",
+    "Croatia",
+    "Cuba",
+    "Cyprus",
+    "Czechia",
+    "Denmark",
+    "Djibouti",
+    "Dominica",
+    "Dominican Republic",
+    "DR Congo",
+    "East Timor",
+    "Ecuador",
+    "Egypt",
+    "El Salvador",
+    "Equatorial Guinea",
+    "Eritrea",
+    "Estonia",
+    "Eswatini",
+    "Ethiopia",
+    "Fiji",
+    "Finland",
+    "France",
+    "Gabon",
+    "Gambia",
+    "Georgia",
+    "Germany",
+    "Ghana",
+    "Greece",
+    "Grenada",
+    "Guatemala",
+    "Guinea",
+    "Guinea-Bissau",
+    "Guyana",
+    "Haiti",
+    "Honduras",
+    "Hungary",
+    "Iceland",
+    "India",
+    "Indonesia",
+    "Iran",
+    "Iraq",
+    "Ireland",
+    "Israel",
+    "Italy",
+    "Ivory Coast",
+    "Jamaica",
+    "Japan",
+    "Jordan",
+    "Kazakhstan",
+    "Kenya",
+    "Kiribati",
+    "Kosovo",
+    "Kuwait",
+    "Kyrgyzstan",
+    "Laos",
+    "Latvia",
+    "Lebanon",
+    "Lesotho",
+    "Liberia",
+    "Libya",
+    "Liechtenstein",
+    "Lithuania",
+    "Luxembourg",
+    "Madagascar",
+    "Malawi",
+    "Malaysia",
+    "Maldives",
+    "Mali",
+    "Malta",
+    "Marshall Islands",
+    "Mauritania",
+    "Mauritius",
+    "Mexico",
+    "Micronesia",
+    "Moldova",
+    "Monaco",
+    "Mongolia",
+    "Montenegro",
+    "Morocco",
+    "Mozambique",
+    "Myanmar",
+    "Namibia",
+    "Nauru",
+    "Nepal",
+    "Netherlands",
+    "New Zealand",
+    "Nicaragua",
+    "Niger",
+    "Nigeria",
+    "North Korea",
+    "North Macedonia",
+    "Norway",
+    "Oman",
+    "Pakistan",
+    "Palau",
+    "Palestine",
+    "Panama",
+    "Papua New Guinea",
+    "Paraguay",
+    "Peru",
+    "Philippines",
+    "Poland",
+    "Portugal",
+    "Qatar",
+    "Romania",
+    "Russia",
+    "Rwanda",
+    "Saint Kitts and Nevis",
+    "Saint Lucia",
+    "Saint Vincent and the Grenadines",
+    "Samoa",
+    "San Marino",
+    "São Tomé and Príncipe",
+    "Saudi Arabia",
+    "Senegal",
+    "Serbia",
+    "Seychelles",
+    "Sierra Leone",
+    "Singapore",
+    "Slovakia",
+    "Slovenia",
+    "Solomon Islands",
+    "Somalia",
+    "South Africa",
+    "South Korea",
+    "South Sudan",
+    "Spain",
+    "Sri Lanka",
+    "Sudan",
+    "Suriname",
+    "Sweden",
+    "Switzerland",
+    "Syria",
+    "Taiwan",
+    "Tajikistan",
+    "Tanzania",
+    "Thailand",
+    "Togo",
+    "Tonga",
+    "Trinidad and Tobago",
+    "Tunisia",
+    "Turkey",
+    "Turkmenistan",
+    "Tuvalu",
+    "Uganda",
+    "UK",
+    "Ukraine",
+    "United Arab Emirates",
+    "Uruguay",
+    "USA",
+    "Uzbekistan",
+    "Vanuatu",
+    "Vatican City",
+    "Venezuela",
+    "Vietnam",
+    "Yemen",
+    "Zambia",
+    "Zimbabwe",
+];
+
+/// Links must be valid absolute URLs, and should use HTTPS.
+pub struct LinkFormat;
+
+impl Rule for LinkFormat {
+    fn name(&self) -> &'static str {
+        "link-format"
+    }
+
+    fn check_event(&self, event: &Event) -> Vec<String> {
+        event
+            .links
+            .iter()
+            .filter_map(|link| match Url::parse(link) {
+                Err(_) => Some(format!("Link '{}' is not a valid URL.", link)),
+                Ok(url) if url.scheme() != "https" => {
+                    Some(format!("Link '{}' doesn't use HTTPS.", link))
+                }
+                Ok(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// Every event should have at least one link where people can find out more.
+pub struct MissingLinks;
+
+impl Rule for MissingLinks {
+    fn name(&self) -> &'static str {
+        "missing-links"
+    }
+
+    fn check_event(&self, event: &Event) -> Vec<String> {
+        if event.links.is_empty() {
+            vec!["Event has no links.".to_string()]
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// A single event lasting several weeks probably has the wrong end date. For a recurring event this
+/// checks each occurrence, as the start and end dates are those of the first.
+pub struct LongDuration;
+
+impl Rule for LongDuration {
+    fn name(&self) -> &'static str {
+        "long-duration"
+    }
+
+    fn check_event(&self, event: &Event) -> Vec<String> {
+        let days = (event.end_date() - event.start_date()).num_days() + 1;
+        if days > MAX_DURATION_DAYS {
+            vec![format!("Event lasts {} days.", days)]
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// An event starting years from now probably has the wrong year.
+pub struct FarFuture;
+
+impl Rule for FarFuture {
+    fn name(&self) -> &'static str {
+        "far-future"
+    }
+
+    fn check_event(&self, event: &Event) -> Vec<String> {
+        let latest = Utc::today().naive_utc() + Duration::days(MAX_DAYS_AHEAD);
+        if event.start_date() > latest {
+            vec![format!(
+                "Event starts on {}, more than {} days from now.",
+                event.start_date(),
+                MAX_DAYS_AHEAD
+            )]
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// Callers should only be listed for events with at least one style of dance which is called.
+pub struct UncalledCallers;
+
+impl Rule for UncalledCallers {
+    fn name(&self) -> &'static str {
+        "uncalled-callers"
+    }
+
+    fn check_event(&self, event: &Event) -> Vec<String> {
+        if !event.callers.is_empty() && !event.styles.iter().any(|style| style.is_called()) {
+            vec![format!(
+                "Event lists callers, but none of its styles ({}) are called.",
+                event
+                    .styles
+                    .iter()
+                    .map(|style| style.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )]
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// Countries should be written consistently, so that filtering by country works.
+pub struct UnknownCountry;
+
+impl Rule for UnknownCountry {
+    fn name(&self) -> &'static str {
+        "unknown-country"
+    }
+
+    fn check_event(&self, event: &Event) -> Vec<String> {
+        if COUNTRIES.contains(&event.country.as_str()) {
+            vec![]
+        } else {
+            vec![format!("Unknown country '{}'.", event.country)]
+        }
+    }
+}
+
+/// Band and city names shouldn't have stray whitespace, and should be written the same way in all
+/// events, so that filtering by them works.
+pub struct NameConsistency;
+
+impl NameConsistency {
+    /// Checks the names returned by `names` for each event, returning a problem for each event
+    /// which uses a spelling different from the most common one.
+    fn check_names<'a>(
+        kind: &str,
+        events: &[&'a Event],
+        names: impl Fn(&'a Event) -> Vec<&'a str>,
+    ) -> Vec<(usize, String)> {
+        // For each normalised name, count how often each spelling is used.
+        let mut spellings: HashMap<String, HashMap<&str, usize>> = HashMap::new();
+        for event in events {
+            for name in names(event) {
+                *spellings
+                    .entry(normalise(name))
+                    .or_default()
+                    .entry(name)
+                    .or_default() += 1;
+            }
+        }
+
+        let mut problems = vec![];
+        for (index, event) in events.iter().enumerate() {
+            for name in names(event) {
+                let counts = &spellings[&normalise(name)];
+                let (preferred, _) = counts
+                    .iter()
+                    .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))
+                    .unwrap();
+                if *preferred != name {
+                    problems.push((
+                        index,
+                        format!(
+                            "{} '{}' is written as '{}' elsewhere.",
+                            kind, name, preferred
+                        ),
+                    ));
+                }
+            }
+        }
+        problems
+    }
+}
+
+impl Rule for NameConsistency {
+    fn name(&self) -> &'static str {
+        "name-consistency"
+    }
+
+    fn check_event(&self, event: &Event) -> Vec<String> {
+        let names = event
+            .bands
+            .iter()
+            .map(|band| ("Band", band))
+            .chain([("City", &event.city)]);
+        names
+            .filter(|(_, name)| name.split_whitespace().collect::<Vec<_>>().join(" ") != **name)
+            .map(|(kind, name)| format!("{} '{}' has extra whitespace.", kind, name))
+            .collect()
+    }
+
+    fn check_all(&self, events: &[&Event]) -> Vec<(usize, String)> {
+        let mut problems = Self::check_names("Band", events, |event| {
+            event.bands.iter().map(String::as_str).collect()
+        });
+        problems.extend(Self::check_names("City", events, |event| {
+            vec![event.city.as_str()]
+        }));
+        problems
+    }
+}
+
+/// Normalises a name for comparison, ignoring case and whitespace differences.
+fn normalise(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::event::EventTime;
+    use chrono::NaiveDate;
+
+    fn event(city: &str, bands: &[&str]) -> Event {
+        Event {
+            name: "Dance".to_string(),
+            links: vec!["http://example.com/".to_string(), "nonsense".to_string()],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd(2022, 1, 1),
+                end_date: NaiveDate::from_ymd(2022, 1, 20),
+            },
+            city: city.to_string(),
+            bands: bands.iter().map(ToString::to_string).collect(),
+            callers: vec!["Caller".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn single_event_rules() {
+        let event = event("Gent", &[]);
+        assert_eq!(
+            LinkFormat.check_event(&event),
+            vec![
+                "Link 'http://example.com/' doesn't use HTTPS.",
+                "Link 'nonsense' is not a valid URL."
+            ]
+        );
+        assert_eq!(
+            LongDuration.check_event(&event),
+            vec!["Event lasts 20 days."]
+        );
+        assert_eq!(UncalledCallers.check_event(&event).len(), 1);
+        assert!(UnknownCountry.check_event(&event).is_empty());
+        assert!(FarFuture.check_event(&event).is_empty());
+        assert!(MissingLinks.check_event(&event).is_empty());
+    }
+
+    #[test]
+    fn inconsistent_names() {
+        let events = [
+            event("Gent", &["The Band"]),
+            event("gent", &["The Band"]),
+            event("Gent ", &["the  band"]),
+        ];
+        let event_refs: Vec<&Event> = events.iter().collect();
+        assert_eq!(
+            NameConsistency.check_all(&event_refs),
+            vec![
+                (
+                    2,
+                    "Band 'the  band' is written as 'The Band' elsewhere.".to_string()
+                ),
+                (1, "City 'gent' is written as 'Gent' elsewhere.".to_string()),
+                (
+                    2,
+                    "City 'Gent ' is written as 'Gent' elsewhere.".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            NameConsistency.check_event(&events[2]),
+            vec![
+                "Band 'the  band' has extra whitespace.",
+                "City 'Gent ' has extra whitespace."
+            ]
+        );
+    }
+}
@@ -12,28 +12,41 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-mod config;
-mod controllers;
-mod errors;
-mod icalendar;
-mod importers;
-mod model;
-
-use crate::{
-    config::Config,
-    controllers::{bands, callers, cities, index, organisations},
-    errors::internal_error,
-    importers::{folkbalbende, webfeet},
-    model::events::Events,
-};
 use axum::{
     routing::{get, get_service},
     AddExtensionLayer, Router,
 };
-use eyre::Report;
-use log::info;
+use chrono::Utc;
+use dancelist::{
+    config::Config,
+    controllers::{admin, bands, callers, cities, event, index, organisations},
+    dedupe,
+    diff::EventsDiff,
+    errors::internal_error,
+    format::{events_yaml, format_events_file, replace_events},
+    importers::{
+        self,
+        rules::Rules,
+        sync::{self, sync_events, MissingPolicy},
+        DateWindow, ImportReport, Imported,
+    },
+    lint,
+    model::{event::Event, events::Events},
+    store::EventStore,
+    validate::{LocatedEvent, Severity, ValidationReport},
+};
+use eyre::{bail, Report, WrapErr};
+use log::{error, info};
 use schemars::schema_for;
-use std::{env, path::Path, process::exit};
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    fs::{read_to_string, write},
+    path::Path,
+    process::exit,
+    time::Duration,
+};
 use tower_http::services::ServeDir;
 
 #[tokio::main]
@@ -49,14 +62,58 @@ async fn main() -> Result<(), Report> {
         // Output JSON schema for events.
         print!("{}", event_schema()?);
         Ok(())
-    } else if args.len() >= 2 && args.len() <= 3 && args[1] == "validate" {
-        validate(args.get(2).map(Path::new))
+    } else if args.len() >= 2 && args[1] == "validate" {
+        let json = args.get(2).map(String::as_str) == Some("--json");
+        let path_args = &args[if json { 3 } else { 2 }..];
+        if path_args.len() > 1 {
+            eprintln!("Invalid command.");
+            exit(1);
+        }
+        validate(path_args.first().map(Path::new), json)
+    } else if args.len() >= 2 && args[1] == "lint" {
+        let json = args.get(2).map(String::as_str) == Some("--json");
+        let path_args = &args[if json { 3 } else { 2 }..];
+        if path_args.len() > 1 {
+            eprintln!("Invalid command.");
+            exit(1);
+        }
+        lint(path_args.first().map(Path::new), json)
+    } else if args.len() >= 2 && args[1] == "dedupe" {
+        let flag = args.get(2).map(String::as_str);
+        let (json, merge) = (flag == Some("--json"), flag == Some("--merge"));
+        let path_args = &args[if json || merge { 3 } else { 2 }..];
+        if path_args.len() > 1 {
+            eprintln!("Invalid command.");
+            exit(1);
+        }
+        dedupe(path_args.first().map(Path::new), json, merge)
+    } else if args.len() >= 2 && args[1] == "fmt" {
+        let check = args.get(2).map(String::as_str) == Some("--check");
+        let path_args = &args[if check { 3 } else { 2 }..];
+        if path_args.len() > 1 {
+            eprintln!("Invalid command.");
+            exit(1);
+        }
+        format(path_args.first().map(Path::new), check)
+    } else if args.len() >= 4 && args.len() <= 5 && args[1] == "diff" {
+        let json = args[2] == "--json";
+        let path_args = &args[if json { 3 } else { 2 }..];
+        if path_args.len() != 2 {
+            eprintln!("Invalid command.");
+            exit(1);
+        }
+        diff(Path::new(&path_args[0]), Path::new(&path_args[1]), json)
     } else if args.len() >= 2 && args.len() <= 3 && args[1] == "cat" {
         concatenate(args.get(2).map(Path::new))
-    } else if args.len() == 2 && args[1] == "balbende" {
-        import_balbende().await
-    } else if args.len() == 2 && args[1] == "webfeet" {
-        import_webfeet().await
+    } else if args.len() == 3 && args[1] == "import" && args[2] == "--list" {
+        list_importers()
+    } else if args.len() >= 3 && args[1] == "import" {
+        if let Some(options) = ImportOptions::parse(&args[3..]) {
+            import(&args[2], &options).await
+        } else {
+            eprintln!("Invalid command.");
+            exit(1);
+        }
     } else {
         eprintln!("Invalid command.");
         exit(1);
@@ -78,10 +135,56 @@ fn load_events(path: Option<&Path>) -> Result<Events, Report> {
     })
 }
 
-fn validate(path: Option<&Path>) -> Result<(), Report> {
-    let events = load_events(path)?;
-    println!("Successfully validated {} events.", events.events.len());
+/// Checks all events in the given file or directory, or in the directory in the config file if no
+/// path is provided, and reports every problem found. Exits with an error status if any of the
+/// problems are errors rather than warnings.
+fn validate(path: Option<&Path>, json: bool) -> Result<(), Report> {
+    let report = if let Some(path) = path {
+        ValidationReport::check_path(path)?
+    } else {
+        let config = Config::from_file()?;
+        ValidationReport::check_path(&config.events_dir)?
+    };
+
+    print_report(&report, json)?;
+    if report.has_errors() {
+        exit(1);
+    }
+    Ok(())
+}
+
+/// Checks all events in the given file or directory, or in the directory in the config file if no
+/// path is provided, with the lint rules enabled in the config file as well as the usual
+/// validation. Exits with an error status if any problems are found.
+fn lint(path: Option<&Path>, json: bool) -> Result<(), Report> {
+    let config = Config::from_file_or_default()?;
+    let rules = lint::enabled_rules(&config.lint)?;
+    let (mut report, events) =
+        ValidationReport::check_path_events(path.unwrap_or(&config.events_dir))?;
+    report.problems.extend(lint::lint(&rules, &events));
+
+    print_report(&report, json)?;
+    if !report.problems.is_empty() {
+        exit(1);
+    }
+    Ok(())
+}
 
+fn print_report(report: &ValidationReport, json: bool) -> Result<(), Report> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for problem in &report.problems {
+            println!("{}", problem);
+        }
+        println!(
+            "Checked {} events in {} files: {} errors, {} warnings.",
+            report.events,
+            report.files,
+            report.count(Severity::Error),
+            report.count(Severity::Warning)
+        );
+    }
     Ok(())
 }
 
@@ -91,40 +194,403 @@ fn concatenate(path: Option<&Path>) -> Result<(), Report> {
     Ok(())
 }
 
-async fn import_balbende() -> Result<(), Report> {
-    let events = folkbalbende::import_events().await?;
-    let yaml = serde_yaml::to_string(&events)?;
-    let yaml = yaml.replacen(
-        "---",
-        "# yaml-language-server: $schema=../events_schema.json",
-        1,
-    );
-    print!("{}", yaml);
+/// Compares the events in two files or directories, and reports which have been added, removed or
+/// changed. Like `diff`, exits with an error status if there are any differences.
+fn diff(old_path: &Path, new_path: &Path, json: bool) -> Result<(), Report> {
+    let old = load_events(Some(old_path))?;
+    let new = load_events(Some(new_path))?;
+    let diff = EventsDiff::compare(&old, &new)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        print!("{}", diff);
+    }
+    if !diff.is_empty() {
+        exit(1);
+    }
+    Ok(())
+}
+
+/// Finds likely duplicate events in the given file or directory, or in the directory in the config
+/// file if no path is provided. Either reports them, or outputs all events with the duplicates
+/// merged.
+fn dedupe(path: Option<&Path>, json: bool, merge: bool) -> Result<(), Report> {
+    let (report, located) = if let Some(path) = path {
+        ValidationReport::check_path_events(path)?
+    } else {
+        let config = Config::from_file()?;
+        ValidationReport::check_path_events(&config.events_dir)?
+    };
+    if report.has_errors() {
+        for problem in &report.problems {
+            eprintln!("{}", problem);
+        }
+        bail!("Fix validation errors before looking for duplicates.");
+    }
+    let events: Vec<&Event> = located.iter().map(|located| &located.event).collect();
+    let duplicates = dedupe::find_duplicates(&events);
+
+    if merge {
+        let groups = dedupe::group_duplicates(&duplicates);
+        let (merged, unmergeable) = dedupe::merge_duplicates(&events, &groups);
+        for group in unmergeable {
+            eprintln!("Can't merge conflicting duplicates:");
+            for index in group {
+                eprintln!("  {}", describe_event(&located[index]));
+            }
+        }
+        eprintln!(
+            "Merged {} events into {}.",
+            events.len(),
+            merged.iter().flatten().count()
+        );
+        write_merged(&located, merged)?;
+    } else if json {
+        let duplicates: Vec<_> = duplicates
+            .iter()
+            .map(|duplicate| {
+                let event_json = |located: &LocatedEvent| {
+                    json!({
+                        "file": located.file,
+                        "line": located.line,
+                        "id": located.event.id(),
+                        "name": located.event.name,
+                    })
+                };
+                json!({
+                    "events": [
+                        event_json(&located[duplicate.first]),
+                        event_json(&located[duplicate.second]),
+                    ],
+                    "reasons": duplicate.reasons,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&duplicates)?);
+    } else {
+        for duplicate in &duplicates {
+            println!("Likely duplicates ({}):", duplicate.reasons.join(", "));
+            println!("  {}", describe_event(&located[duplicate.first]));
+            println!("  {}", describe_event(&located[duplicate.second]));
+        }
+        println!(
+            "Found {} likely duplicates among {} events.",
+            duplicates.len(),
+            events.len()
+        );
+    }
+    Ok(())
+}
+
+/// Rewrites all events files in the given file or directory, or in the directory in the config file
+/// if no path is provided, in canonical form. If `check` is true then the files are left unchanged,
+/// and it exits with an error status if any of them aren't already formatted.
+fn format(path: Option<&Path>, check: bool) -> Result<(), Report> {
+    let path = if let Some(path) = path {
+        path.to_owned()
+    } else {
+        Config::from_file()?.events_dir
+    };
+    let filenames = if path.is_dir() {
+        Events::files_in_directory(&path)?
+    } else {
+        vec![path]
+    };
+
+    let mut unformatted = 0;
+    for filename in &filenames {
+        let contents =
+            read_to_string(filename).wrap_err_with(|| format!("Reading {:?}", filename))?;
+        let formatted =
+            format_events_file(&contents).wrap_err_with(|| format!("Formatting {:?}", filename))?;
+        if formatted != contents {
+            unformatted += 1;
+            if check {
+                println!("{} is not formatted.", filename.display());
+            } else {
+                write(filename, formatted).wrap_err_with(|| format!("Writing {:?}", filename))?;
+                println!("Formatted {}.", filename.display());
+            }
+        }
+    }
+
+    if check && unformatted > 0 {
+        exit(1);
+    }
+    Ok(())
+}
+
+/// Writes the results of merging duplicates back to the files which the events came from.
+/// `merged[i]` is the event to replace `located[i]` with, or `None` to remove it. Only files with
+/// changes are written.
+fn write_merged(located: &[LocatedEvent], merged: Vec<Option<Event>>) -> Result<(), Report> {
+    // The events of each file, along with their index in the file.
+    let mut files: BTreeMap<&Path, Vec<(usize, Option<Event>)>> = BTreeMap::new();
+    let mut changed = BTreeSet::new();
+    for (located, merged) in located.iter().zip(merged) {
+        if merged.as_ref() != Some(&located.event) {
+            changed.insert(located.file.as_path());
+        }
+        let file_events = files.entry(&located.file).or_default();
+        file_events.push((file_events.len(), merged));
+    }
+
+    for filename in changed {
+        let (origins, events): (Vec<Option<usize>>, Vec<Event>) = files
+            .remove(filename)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(index, event)| Some((Some(index), event?)))
+            .unzip();
+        let contents =
+            read_to_string(filename).wrap_err_with(|| format!("Reading {:?}", filename))?;
+        let contents = replace_events(&contents, events, &origins)
+            .wrap_err_with(|| format!("Formatting {:?}", filename))?;
+        write(filename, contents).wrap_err_with(|| format!("Writing {:?}", filename))?;
+        println!("Wrote merged events to {}.", filename.display());
+    }
+    Ok(())
+}
+
+/// Describes the given event and where it was defined, on a single line.
+fn describe_event(located: &LocatedEvent) -> String {
+    let line = located
+        .line
+        .map(|line| format!(":{}", line))
+        .unwrap_or_default();
+    format!(
+        "{}{}: '{}' on {}",
+        located.file.display(),
+        line,
+        located.event.name,
+        located.event.start_date()
+    )
+}
+
+fn list_importers() -> Result<(), Report> {
+    let config = Config::from_file_or_default()?;
+    let importers = importers::all_importers(&config.importers);
+    let width = importers
+        .iter()
+        .map(|importer| importer.name().len())
+        .max()
+        .unwrap_or_default();
+    for importer in importers {
+        println!(
+            "{:width$} {}",
+            importer.name(),
+            importer.description(),
+            width = width
+        );
+    }
     Ok(())
 }
 
-async fn import_webfeet() -> Result<(), Report> {
-    let events = webfeet::import_events().await?;
-    let yaml = serde_yaml::to_string(&events)?;
-    let yaml = yaml.replacen(
-        "---",
-        "# yaml-language-server: $schema=../events_schema.json",
-        1,
+/// Options for the `import` command.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct ImportOptions<'a> {
+    /// Files or URLs from which to read the raw event data rather than fetching it from the
+    /// configured source, or "-" for stdin.
+    inputs: Vec<&'a str>,
+    /// A file in which to save the raw event data.
+    save_raw: Option<&'a Path>,
+    /// An existing events file into which to merge the imported events.
+    into: Option<&'a Path>,
+    /// A file in which to save a JSON report of the import, including all warnings.
+    report: Option<&'a Path>,
+}
+
+impl<'a> ImportOptions<'a> {
+    /// Parses the given command-line arguments, or returns `None` if they aren't valid. Arguments
+    /// which aren't flags are taken as inputs, as is the value of each `--input` flag.
+    fn parse(args: &'a [String]) -> Option<Self> {
+        let mut options = Self::default();
+        let mut args = args.iter().map(String::as_str);
+        while let Some(arg) = args.next() {
+            match arg {
+                "--input" => options.inputs.push(args.next()?),
+                "--save-raw" => options.save_raw = Some(Path::new(args.next()?)),
+                "--into" => options.into = Some(Path::new(args.next()?)),
+                "--report" => options.report = Some(Path::new(args.next()?)),
+                flag if flag.starts_with("--") => return None,
+                input => options.inputs.push(input),
+            }
+        }
+        Some(options)
+    }
+}
+
+/// Imports events from the given source, and reports any warnings. If a file is given then the
+/// events are merged into it, keeping any local edits, otherwise they are written to stdout.
+async fn import(source: &str, options: &ImportOptions<'_>) -> Result<(), Report> {
+    let config = Config::from_file_or_default()?;
+    let importer = importers::find_importer(source, &config.importers)?;
+    let source_config = config.importers.source(importer.name());
+    let today = Utc::today().naive_utc();
+    let window = DateWindow::for_source(&source_config, today);
+    let raws = if options.inputs.is_empty() {
+        vec![importers::fetch(importer.as_ref(), &source_config, &window).await?]
+    } else {
+        let mut raws = vec![];
+        for input in &options.inputs {
+            raws.push(importers::read_input(input).await?);
+        }
+        raws
+    };
+    if let Some(save_raw) = options.save_raw {
+        if raws.len() > 1 {
+            bail!("--save-raw can't be used with more than one input.");
+        }
+        write(save_raw, &raws[0]).wrap_err_with(|| format!("Writing {:?}", save_raw))?;
+    }
+    let mut imported = Imported::default();
+    for raw in &raws {
+        let converted = importer
+            .convert(raw)
+            .wrap_err_with(|| format!("Converting events from {}", importer.name()))?;
+        imported.events.events.extend(converted.events.events);
+        imported.warnings.extend(converted.warnings);
+    }
+    let rules = Rules::for_importer(importer.as_ref(), &source_config)?;
+    let changed_by_rules = rules.apply(&mut imported);
+    imported.drop_invalid();
+    imported.set_sources(importer.as_ref(), &source_config, Utc::now());
+    let converted_count = imported.events.events.len();
+    imported
+        .events
+        .events
+        .retain(|event| window.contains(event));
+    let outside_window = converted_count - imported.events.events.len();
+
+    let report = ImportReport {
+        importer: importer.name().to_owned(),
+        imported: imported.events.events.len(),
+        outside_window,
+        changed_by_rules,
+        warnings: imported.warnings,
+    };
+    for warning in &report.warnings {
+        eprintln!("{}", warning);
+    }
+    eprintln!("{}", report.summary());
+    if let Some(report_path) = options.report {
+        write(report_path, serde_json::to_string_pretty(&report)?)
+            .wrap_err_with(|| format!("Writing {:?}", report_path))?;
+    }
+
+    if let Some(into) = options.into {
+        // Events which are already over or outside the window aren't expected to be upstream.
+        let expected = DateWindow {
+            start: Some(window.start.map_or(today, |start| start.max(today))),
+            end: window.end,
+        };
+        import_into(
+            into,
+            &imported.events,
+            |event| importers::upstream_id(importer.as_ref(), event),
+            config.importers.missing,
+            &expected,
+        )
+    } else {
+        print!("{}", events_yaml(&imported.events)?);
+        Ok(())
+    }
+}
+
+/// Merges the imported events into the given file, creating it if it doesn't exist yet. A copy of
+/// the imported events is kept alongside it, so that the next import can tell which fields have
+/// been edited locally.
+fn import_into(
+    path: &Path,
+    imported: &Events,
+    source_id: impl Fn(&Event) -> Option<String>,
+    missing: MissingPolicy,
+    expected: &DateWindow,
+) -> Result<(), Report> {
+    let contents = if path.exists() {
+        read_to_string(path).wrap_err_with(|| format!("Reading {:?}", path))?
+    } else {
+        String::new()
+    };
+    let existing: Events = if contents.is_empty() {
+        Events::default()
+    } else {
+        serde_yaml::from_str(&contents).wrap_err_with(|| format!("Reading {:?}", path))?
+    };
+    let base_path = sync::base_path(path);
+    let base: Option<Events> = if base_path.exists() {
+        let base_contents =
+            read_to_string(&base_path).wrap_err_with(|| format!("Reading {:?}", base_path))?;
+        Some(
+            serde_yaml::from_str(&base_contents)
+                .wrap_err_with(|| format!("Reading {:?}", base_path))?,
+        )
+    } else {
+        if !existing.events.is_empty() {
+            eprintln!(
+                "No previous import found at {:?}, so keeping all existing values.",
+                base_path
+            );
+        }
+        None
+    };
+
+    let sync = sync_events(
+        &existing,
+        base.as_ref(),
+        imported,
+        source_id,
+        missing,
+        expected,
+    )?;
+    for field in &sync.overridden {
+        eprintln!("Keeping local edit to {}.", field);
+    }
+    for name in &sync.without_base {
+        eprintln!(
+            "Not updating '{}' as there is no previous import to tell upstream changes from local \
+             edits.",
+            name
+        );
+    }
+    let output = replace_events(&contents, sync.events, &sync.origins)?;
+    write(path, output).wrap_err_with(|| format!("Writing {:?}", path))?;
+    write(&base_path, events_yaml(&Events { events: sync.base })?)
+        .wrap_err_with(|| format!("Writing {:?}", base_path))?;
+    println!(
+        "{} added, {} updated, {} unchanged, {} no longer upstream ({:?}).",
+        sync.added, sync.updated, sync.unchanged, sync.missing, missing
     );
-    print!("{}", yaml);
+    if !sync.without_base.is_empty() {
+        println!(
+            "Warning: {} events differ from upstream but weren't updated because {:?} is missing.",
+            sync.without_base.len(),
+            base_path
+        );
+    }
     Ok(())
 }
 
 async fn serve() -> Result<(), Report> {
     let config = Config::from_file()?;
-    let events = Events::load_directory(&config.events_dir)?;
+    let store = EventStore::load(&config.events_dir)?;
+    let watch = store
+        .clone()
+        .watch(Duration::from_secs(config.reload_interval_secs));
+    tokio::spawn(async move {
+        if let Err(e) = watch.await {
+            error!("Stopped watching for changes to events: {:#}", e);
+        }
+    });
 
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/", get(index::index))
+        .route("/index.csv", get(index::index_csv))
         .route("/index.ics", get(index::index_ics))
         .route("/index.json", get(index::index_json))
         .route("/index.toml", get(index::index_toml))
         .route("/index.yaml", get(index::index_yaml))
+        .route("/events/:id", get(event::event))
         .route("/bands", get(bands::bands))
         .route("/callers", get(callers::callers))
         .route("/cities", get(cities::cities))
@@ -133,8 +599,11 @@ async fn serve() -> Result<(), Report> {
             "/stylesheets",
             get_service(ServeDir::new(config.public_dir.join("stylesheets")))
                 .handle_error(internal_error),
-        )
-        .layer(AddExtensionLayer::new(events));
+        );
+    if config.admin_status {
+        app = app.route("/admin/status", get(admin::status));
+    }
+    let app = app.layer(AddExtensionLayer::new(store));
 
     info!("Listening on {}", config.bind_address);
     axum::Server::bind(&config.bind_address)
@@ -153,7 +622,6 @@ fn event_schema() -> Result<String, Report> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs::read_to_string;
 
     #[test]
     fn json_schema_matches() {
@@ -80,6 +80,20 @@ impl DanceStyle {
             Self::Scandinavian => "scandi",
         }
     }
+
+    /// Returns whether dances in this style are usually called, so that it makes sense for events
+    /// in the style to list callers.
+    pub fn is_called(self) -> bool {
+        matches!(
+            self,
+            Self::Contra
+                | Self::EnglishCeilidh
+                | Self::IrishCeili
+                | Self::IrishSet
+                | Self::Playford
+                | Self::ScottishCeilidh
+        )
+    }
 }
 
 impl Display for DanceStyle {
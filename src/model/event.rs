@@ -12,8 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::dancestyle::DanceStyle;
-use chrono::{Date, DateTime, Datelike, FixedOffset, NaiveDate, Utc};
+use super::{
+    dancestyle::DanceStyle,
+    price::{self, Price, PriceRepr},
+    recurrence::Recurrence,
+    source::Source,
+};
+use chrono::{Date, DateTime, Datelike, Duration, FixedOffset, NaiveDate, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use deunicode::deunicode;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::ops::Not;
@@ -22,8 +29,12 @@ use std::ops::Not;
 const FACEBOOK_EVENT_PREFIX: &str = "https://www.facebook.com/events/";
 const FBB_EVENT_PREFIX: &str = "https://folkbalbende.be/event/";
 
-#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct Event {
+    /// A unique identifier for the event, used in its URL. If this is not given then one is derived
+    /// from the start date, name and city of the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     /// The name of the event.
     pub name: String,
     /// More details describing the event.
@@ -34,10 +45,16 @@ pub struct Event {
     pub links: Vec<String>,
     #[serde(flatten)]
     pub time: EventTime,
-    // TODO: Should start and end require time or just date? What about timezone?
+    // TODO: Should start and end require time or just date?
+    /// The IANA name of the timezone in which the event takes place, e.g. "Europe/London".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub timezone: Option<Tz>,
     pub country: String,
     pub city: String,
-    // TODO: What about full address?
+    /// The region, county or state within the country, if it helps to identify the location.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
     /// The dance styles included in the event.
     #[serde(default)]
     pub styles: Vec<DanceStyle>,
@@ -53,16 +70,42 @@ pub struct Event {
     /// The names of the callers calling at the event, if applicable.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub callers: Vec<String>,
-    /// The price or price range of the event, if available.
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub price: Option<String>,
-    // TODO: Should free events be distinguished from events with unknown price?
+    /// The names of the teachers leading workshops at the event, if any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub teachers: Vec<String>,
+    /// The price or price range of the event, if known.
+    #[serde(
+        default,
+        deserialize_with = "price::deserialize_option",
+        serialize_with = "price::serialize_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<PriceRepr>")]
+    pub price: Option<Price>,
     /// The organisation who run the event.
     #[serde(default)]
     pub organisation: Option<String>,
     /// Whether the event has been cancelled.
     #[serde(default, skip_serializing_if = "Not::not")]
     pub cancelled: bool,
+    /// Whether the date or other details of the event are uncertain, and yet to be confirmed.
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub tentative: bool,
+    /// The venue where the event takes place, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub venue: Option<Venue>,
+    /// The workshops, sets and other sessions within the event, if known.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedule: Vec<Session>,
+    /// More information about the bands listed in `bands`, if known.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub band_details: Vec<BandDetails>,
+    /// How the event repeats, if it is a recurring event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Recurrence>,
+    /// Where the event was imported from, if it was imported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
@@ -81,12 +124,191 @@ pub enum EventTime {
     },
 }
 
+impl EventTime {
+    /// Moves the event time by the given number of days. If a timezone is given then the local
+    /// time of day is kept the same, even if the offset changes.
+    fn shifted(&self, days: Duration, timezone: Option<Tz>) -> Self {
+        match self {
+            EventTime::DateOnly {
+                start_date,
+                end_date,
+            } => EventTime::DateOnly {
+                start_date: *start_date + days,
+                end_date: *end_date + days,
+            },
+            EventTime::DateTime { start, end } => EventTime::DateTime {
+                start: shift_date_time(*start, days, timezone),
+                end: shift_date_time(*end, days, timezone),
+            },
+        }
+    }
+}
+
+/// The venue where an event takes place. The city and country under which the event is listed are
+/// given by the event itself; the venue only needs its own if they are different, e.g. for a hall
+/// in a village just outside the city.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Venue {
+    /// The name of the venue, e.g. "St Peter's Church Hall".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The street address of the venue, including the house number if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub street: Option<String>,
+    /// The postcode or ZIP code of the venue.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postcode: Option<String>,
+    /// The city, town or village of the venue, if different from the city of the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    /// The country of the venue, if different from the country of the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// The latitude of the venue, in degrees north.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    /// The longitude of the venue, in degrees east.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+}
+
+impl Venue {
+    /// Check that the venue information is valid. Returns an empty list if it is, or a list of
+    /// problems if not.
+    pub fn validate(&self) -> Vec<&'static str> {
+        let mut problems = vec![];
+
+        if self.name.is_none() && self.street.is_none() {
+            problems.push("Venue must have a name or a street address.");
+        }
+
+        match (self.latitude, self.longitude) {
+            (Some(latitude), Some(longitude)) => {
+                if !(-90.0..=90.0).contains(&latitude) {
+                    problems.push("Venue latitude must be between -90 and 90.");
+                }
+                if !(-180.0..=180.0).contains(&longitude) {
+                    problems.push("Venue longitude must be between -180 and 180.");
+                }
+            }
+            (None, None) => {}
+            _ => problems.push("Venue must have both latitude and longitude, or neither."),
+        }
+
+        problems
+    }
+
+    /// Formats the name and street address of the venue on a single line. The city and country are
+    /// left out, as they are shown along with the event's; see `Event::location`.
+    pub fn address(&self) -> String {
+        [&self.name, &self.street, &self.postcode]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Gets the latitude and longitude of the venue, if both are known.
+    pub fn coordinates(&self) -> Option<(f64, f64)> {
+        Some((self.latitude?, self.longitude?))
+    }
+
+    /// Gets a link to the venue on OpenStreetMap, if its coordinates are known.
+    pub fn map_url(&self) -> Option<String> {
+        self.coordinates().map(|(latitude, longitude)| {
+            format!(
+                "https://www.openstreetmap.org/?mlat={}&mlon={}#map=17/{}/{}",
+                latitude, longitude, latitude, longitude
+            )
+        })
+    }
+}
+
+/// A session within an event, such as a workshop or a band's set.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Session {
+    /// What happens in the session, e.g. the title of a workshop or the name of a band.
+    pub name: String,
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+    /// The names of the teachers leading the session, if it is a workshop.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub teachers: Vec<String>,
+}
+
+impl Session {
+    /// Moves the session by the given number of days, keeping the same local time of day if a
+    /// timezone is given.
+    fn shifted(&self, days: Duration, timezone: Option<Tz>) -> Self {
+        Self {
+            start: shift_date_time(self.start, days, timezone),
+            end: shift_date_time(self.end, days, timezone),
+            ..self.clone()
+        }
+    }
+}
+
+/// Details of a band playing at an event.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BandDetails {
+    /// The name of the band, as given in the event's `bands`.
+    pub name: String,
+    /// The country which the band comes from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// The musicians in the band.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub musicians: Vec<Musician>,
+}
+
+/// A musician in a band.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Musician {
+    pub name: String,
+    /// The instruments which the musician plays, e.g. "fiddle, accordion".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instruments: Option<String>,
+    /// The country which the musician comes from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+}
+
+impl BandDetails {
+    /// Formats the musicians and their instruments on a single line, e.g.
+    /// "Jane Smith (fiddle), John Smith (accordion)".
+    pub fn musicians_summary(&self) -> String {
+        self.musicians
+            .iter()
+            .map(|musician| match &musician.instruments {
+                Some(instruments) => format!("{} ({})", musician.name, instruments),
+                None => musician.name.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
 impl Event {
     /// Check that the event information is valid. Returns an empty list if it is, or a list of
     /// problems if not.
     pub fn validate(&self) -> Vec<&'static str> {
         let mut problems = vec![];
 
+        if let Some(id) = &self.id {
+            if id.is_empty() || slugify(id) != *id {
+                problems.push("ID must contain only lowercase letters, digits and single hyphens.");
+            }
+        }
+
+        if self.country.is_empty() {
+            problems.push("Country must not be empty.");
+        }
+
         if !self.workshop && !self.social {
             problems.push("Must have at least a workshop or a social.")
         }
@@ -104,6 +326,14 @@ impl Event {
                 if start > end {
                     problems.push("Start must be before or equal to end.");
                 }
+                if let Some(timezone) = self.timezone {
+                    if start.offset() != &offset_in(timezone, start) {
+                        problems.push("Start offset must match the offset of the timezone.");
+                    }
+                    if end.offset() != &offset_in(timezone, end) {
+                        problems.push("End offset must match the offset of the timezone.");
+                    }
+                }
             }
         }
 
@@ -111,91 +341,215 @@ impl Event {
             problems.push("Must include at least one style of dance.")
         }
 
+        if self
+            .schedule
+            .iter()
+            .any(|session| session.start > session.end)
+        {
+            problems.push("Sessions must start before or when they end.");
+        }
+
+        if let Some(venue) = &self.venue {
+            problems.extend(venue.validate());
+        }
+
+        if let Some(recurrence) = &self.recurrence {
+            problems.extend(recurrence.validate(self.start_date()));
+        }
+
         problems
     }
 
     /// Merge this event and the other into a combined one, if they are similar enough.
     pub fn merge(&self, other: &Event) -> Option<Event> {
-        if self.name == other.name
+        if self.id == other.id
+            && self.name == other.name
             && self.time == other.time
+            && self.timezone == other.timezone
+            && self.recurrence == other.recurrence
             && self.country == other.country
             && self.city == other.city
         {
-            let mut links = self.links.clone();
-            links.extend(other.links.clone());
-            links.dedup();
-
-            let mut styles = self.styles.clone();
-            styles.extend(other.styles.clone());
-            styles.sort();
-            styles.dedup();
-
-            let mut bands = self.bands.clone();
-            bands.extend(other.bands.clone());
-            bands.sort();
-            bands.dedup();
-
-            let mut callers = self.callers.clone();
-            callers.extend(other.callers.clone());
-            callers.sort();
-            callers.dedup();
-
-            let details = match (&self.details, &other.details) {
-                (None, None) => None,
-                (Some(d), None) | (None, Some(d)) => Some(d.clone()),
-                (Some(a), Some(b)) => {
-                    if a == b {
-                        Some(a.clone())
-                    } else {
-                        Some(format!("{}\n{}", a, b))
-                    }
+            self.combine(other)
+        } else {
+            None
+        }
+    }
+
+    /// Combines this event with another describing the same event. The name, time and location of
+    /// this event are kept, and other details are taken from both. Returns `None` if the events have
+    /// conflicting prices, venues or organisations.
+    pub fn combine(&self, other: &Event) -> Option<Event> {
+        let mut links = self.links.clone();
+        for link in &other.links {
+            if !links.contains(link) {
+                links.push(link.clone());
+            }
+        }
+
+        let mut styles = self.styles.clone();
+        styles.extend(other.styles.clone());
+        styles.sort();
+        styles.dedup();
+
+        let mut bands = self.bands.clone();
+        bands.extend(other.bands.clone());
+        bands.sort();
+        bands.dedup();
+
+        let mut callers = self.callers.clone();
+        callers.extend(other.callers.clone());
+        callers.sort();
+        callers.dedup();
+
+        let mut teachers = self.teachers.clone();
+        teachers.extend(other.teachers.clone());
+        teachers.sort();
+        teachers.dedup();
+
+        let mut schedule = self.schedule.clone();
+        for session in &other.schedule {
+            if !schedule.contains(session) {
+                schedule.push(session.clone());
+            }
+        }
+        schedule.sort_by_key(|session| session.start);
+
+        let mut band_details = self.band_details.clone();
+        for band in &other.band_details {
+            if !band_details
+                .iter()
+                .any(|existing| existing.name == band.name)
+            {
+                band_details.push(band.clone());
+            }
+        }
+
+        let details = match (&self.details, &other.details) {
+            (None, None) => None,
+            (Some(d), None) | (None, Some(d)) => Some(d.clone()),
+            (Some(a), Some(b)) => {
+                if a == b {
+                    Some(a.clone())
+                } else {
+                    Some(format!("{}\n{}", a, b))
                 }
-            };
-
-            let price = match (&self.price, &other.price) {
-                (None, None) => None,
-                (Some(p), None) | (None, Some(p)) => Some(p.clone()),
-                (Some(a), Some(b)) => {
-                    if a == b {
-                        Some(a.clone())
-                    } else {
-                        // Can't merge different prices.
-                        return None;
-                    }
+            }
+        };
+
+        let price = match (&self.price, &other.price) {
+            (None, None) => None,
+            (Some(p), None) | (None, Some(p)) => Some(p.clone()),
+            (Some(a), Some(b)) => {
+                if a == b {
+                    Some(a.clone())
+                } else {
+                    // Can't merge different prices.
+                    return None;
+                }
+            }
+        };
+
+        let venue = match (&self.venue, &other.venue) {
+            (None, None) => None,
+            (Some(v), None) | (None, Some(v)) => Some(v.clone()),
+            (Some(a), Some(b)) => {
+                if a == b {
+                    Some(a.clone())
+                } else {
+                    // Can't merge different venues.
+                    return None;
                 }
-            };
-
-            let organisation = match (&self.organisation, &other.organisation) {
-                (None, None) => None,
-                (Some(o), None) | (None, Some(o)) => Some(o.clone()),
-                (Some(a), Some(b)) => {
-                    if a == b {
-                        Some(a.clone())
-                    } else {
-                        // Can't merge different organisations.
-                        return None;
+            }
+        };
+
+        let organisation = match (&self.organisation, &other.organisation) {
+            (None, None) => None,
+            (Some(o), None) | (None, Some(o)) => Some(o.clone()),
+            (Some(a), Some(b)) => {
+                if a == b {
+                    Some(a.clone())
+                } else {
+                    // Can't merge different organisations.
+                    return None;
+                }
+            }
+        };
+
+        Some(Event {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            details,
+            links,
+            time: self.time.clone(),
+            timezone: self.timezone,
+            country: self.country.clone(),
+            city: self.city.clone(),
+            region: self.region.clone(),
+            styles,
+            workshop: self.workshop || other.workshop,
+            social: self.social || other.social,
+            bands,
+            callers,
+            teachers,
+            price,
+            organisation,
+            cancelled: self.cancelled || other.cancelled,
+            tentative: self.tentative || other.tentative,
+            venue,
+            schedule,
+            band_details,
+            recurrence: self.recurrence.clone(),
+            source: self.source.clone().or_else(|| other.source.clone()),
+        })
+    }
+
+    /// Expands a recurring event into its individual occurrences. Events which don't recur are
+    /// returned unchanged.
+    pub fn occurrences(mut self) -> Vec<Event> {
+        let recurrence = if let Some(recurrence) = self.recurrence.take() {
+            recurrence
+        } else {
+            return vec![self];
+        };
+
+        let first = self.start_date();
+        recurrence
+            .dates(first)
+            .into_iter()
+            .map(|date| {
+                let mut occurrence = Event {
+                    id: self.id.as_ref().map(|id| format!("{}-{}", id, date)),
+                    time: self.time.shifted(date - first, self.timezone),
+                    schedule: self
+                        .schedule
+                        .iter()
+                        .map(|session| session.shifted(date - first, self.timezone))
+                        .collect(),
+                    ..self.clone()
+                };
+                for date_override in &recurrence.overrides {
+                    if date_override.date == date {
+                        date_override.apply(&mut occurrence);
                     }
                 }
-            };
-
-            Some(Event {
-                name: self.name.clone(),
-                details,
-                links,
-                time: self.time.clone(),
-                country: self.country.clone(),
-                city: self.city.clone(),
-                styles,
-                workshop: self.workshop || other.workshop,
-                social: self.social || other.social,
-                bands,
-                callers,
-                price,
-                organisation,
-                cancelled: self.cancelled || other.cancelled,
+                occurrence
             })
+            .collect()
+    }
+
+    /// Gets the event's unique identifier, either as given explicitly or derived from its start
+    /// date, name and city.
+    pub fn id(&self) -> String {
+        if let Some(id) = &self.id {
+            id.to_owned()
         } else {
-            None
+            slugify(&format!(
+                "{} {} {}",
+                self.start_date(),
+                self.name,
+                self.city
+            ))
         }
     }
 
@@ -239,6 +593,31 @@ impl Event {
         links
     }
 
+    /// Formats the full location of the event, including the venue if known.
+    pub fn location(&self) -> String {
+        let mut parts = vec![];
+        let (mut city, mut country) = (&self.city, &self.country);
+        if let Some(venue) = &self.venue {
+            parts.push(venue.address());
+            // The venue's own city or country replaces the one the event is listed under.
+            city = venue.city.as_ref().unwrap_or(city);
+            country = venue.country.as_ref().unwrap_or(country);
+        }
+        parts.push(city.clone());
+        parts.extend(self.region.clone());
+        parts.push(country.clone());
+        parts.join(", ")
+    }
+
+    /// Converts the given time to the event's timezone, if it has one.
+    fn local_time(&self, time: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        if let Some(timezone) = self.timezone {
+            time.with_timezone(&offset_in(timezone, time))
+        } else {
+            time
+        }
+    }
+
     /// Checks whether the event lasts more than one day.
     pub fn multiday(&self) -> bool {
         match self.time {
@@ -246,7 +625,9 @@ impl Event {
                 start_date,
                 end_date,
             } => start_date != end_date,
-            EventTime::DateTime { start, end } => start.date() != end.date(),
+            EventTime::DateTime { start, end } => {
+                self.local_time(start).date() != self.local_time(end).date()
+            }
         }
     }
 
@@ -261,6 +642,28 @@ impl Event {
         }
     }
 
+    /// Gets the date on which the event starts, in its local timezone.
+    pub fn start_date(&self) -> NaiveDate {
+        match self.time {
+            EventTime::DateOnly {
+                start_date,
+                end_date: _,
+            } => start_date,
+            EventTime::DateTime { start, end: _ } => self.local_time(start).naive_local().date(),
+        }
+    }
+
+    /// Gets the date on which the event ends, in its local timezone.
+    pub fn end_date(&self) -> NaiveDate {
+        match self.time {
+            EventTime::DateOnly {
+                start_date: _,
+                end_date,
+            } => end_date,
+            EventTime::DateTime { start: _, end } => self.local_time(end).naive_local().date(),
+        }
+    }
+
     /// Gets the year in which the event starts.
     pub fn start_year(&self) -> i32 {
         match self.time {
@@ -268,7 +671,7 @@ impl Event {
                 start_date,
                 end_date: _,
             } => start_date.year(),
-            EventTime::DateTime { start, end: _ } => start.year(),
+            EventTime::DateTime { start, end: _ } => self.local_time(start).year(),
         }
     }
 
@@ -279,7 +682,61 @@ impl Event {
                 start_date,
                 end_date: _,
             } => start_date.month(),
-            EventTime::DateTime { start, end: _ } => start.month(),
+            EventTime::DateTime { start, end: _ } => self.local_time(start).month(),
+        }
+    }
+
+    /// Formats the event start date/time, and end date/time if it is different, including the
+    /// month and year.
+    pub fn full_time(&self) -> String {
+        match self.time {
+            EventTime::DateOnly {
+                start_date,
+                end_date,
+            } => {
+                if !self.multiday() {
+                    start_date.format("%A %-d %B %Y").to_string()
+                } else {
+                    format!(
+                        "{} – {}",
+                        start_date.format("%A %-d %B %Y"),
+                        end_date.format("%A %-d %B %Y")
+                    )
+                }
+            }
+            EventTime::DateTime { start, end } => {
+                let start = self.local_time(start);
+                let end = self.local_time(end);
+                if !self.multiday() {
+                    format!(
+                        "{} – {}",
+                        start.format("%A %-d %B %Y %l:%M %P"),
+                        end.format("%l:%M %P")
+                    )
+                } else {
+                    format!(
+                        "{} – {}",
+                        start.format("%A %-d %B %Y %l:%M %P"),
+                        end.format("%A %-d %B %Y %l:%M %P")
+                    )
+                }
+            }
+        }
+    }
+
+    /// Formats the start and end time of a session in the event's timezone, including the day if
+    /// the event lasts more than one day.
+    pub fn session_time(&self, session: &Session) -> String {
+        let start = self.local_time(session.start);
+        let end = self.local_time(session.end);
+        if self.multiday() {
+            format!(
+                "{}–{}",
+                start.format("%a %e %l:%M %P"),
+                end.format("%l:%M %P")
+            )
+        } else {
+            format!("{}–{}", start.format("%l:%M %P"), end.format("%l:%M %P"))
         }
     }
 
@@ -308,6 +765,8 @@ impl Event {
                 }
             }
             EventTime::DateTime { start, end } => {
+                let start = self.local_time(start);
+                let end = self.local_time(end);
                 if !self.multiday() {
                     format!(
                         "{}–{}",
@@ -332,8 +791,169 @@ impl Event {
     }
 }
 
+/// A valid single-day balfolk event, for tests to build fixtures from with struct update syntax so
+/// that they don't all need changing whenever a field is added.
+#[cfg(test)]
+impl Default for Event {
+    fn default() -> Self {
+        Self {
+            id: None,
+            name: "Dance".to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd(2022, 1, 1),
+                end_date: NaiveDate::from_ymd(2022, 1, 1),
+            },
+            timezone: None,
+            country: "Belgium".to_string(),
+            city: "Gent".to_string(),
+            region: None,
+            styles: vec![DanceStyle::Balfolk],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            teachers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            tentative: false,
+            venue: None,
+            schedule: vec![],
+            band_details: vec![],
+            recurrence: None,
+            source: None,
+        }
+    }
+}
+
+/// Converts the given string to lowercase ASCII letters and digits separated by single hyphens,
+/// suitable for use in a URL. Accented and other non-ASCII letters are transliterated to ASCII.
+fn slugify(s: &str) -> String {
+    deunicode(s)
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Gets the fixed offset of the given timezone at the given instant.
+fn offset_in(timezone: Tz, time: DateTime<FixedOffset>) -> FixedOffset {
+    time.with_timezone(&timezone).offset().fix()
+}
+
+/// Moves the given time by the given number of days, keeping the same local time in the given
+/// timezone if any.
+fn shift_date_time(
+    time: DateTime<FixedOffset>,
+    days: Duration,
+    timezone: Option<Tz>,
+) -> DateTime<FixedOffset> {
+    if let Some(timezone) = timezone {
+        let local = time.with_timezone(&timezone).naive_local() + days;
+        // If the local time doesn't exist on the new day because of a DST change then fall back to
+        // keeping the same offset.
+        if let Some(shifted) = timezone.from_local_datetime(&local).earliest() {
+            return shifted.with_timezone(&shifted.offset().fix());
+        }
+    }
+    time + days
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Link {
     pub short_name: String,
     pub url: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn venue_validation() {
+        let venue = Venue {
+            name: Some("Hall".to_string()),
+            latitude: Some(51.0),
+            longitude: Some(3.7),
+            ..Default::default()
+        };
+        assert_eq!(venue.validate(), Vec::<&str>::new());
+        assert_eq!(
+            Venue {
+                latitude: Some(91.0),
+                ..venue.clone()
+            }
+            .validate(),
+            vec!["Venue latitude must be between -90 and 90."]
+        );
+        assert_eq!(
+            Venue {
+                longitude: None,
+                ..venue
+            }
+            .validate(),
+            vec!["Venue must have both latitude and longitude, or neither."]
+        );
+        assert_eq!(
+            Venue::default().validate(),
+            vec!["Venue must have a name or a street address."]
+        );
+    }
+
+    #[test]
+    fn validate_country() {
+        let event = Event {
+            country: "UK".to_string(),
+            ..Default::default()
+        };
+        assert!(!event.validate().contains(&"Country must not be empty."));
+        let event = Event {
+            country: String::new(),
+            ..event
+        };
+        assert!(event.validate().contains(&"Country must not be empty."));
+    }
+
+    #[test]
+    fn venue_location() {
+        let venue = Venue {
+            name: Some("Zaal De Kring".to_string()),
+            street: Some("Kerkstraat 1".to_string()),
+            postcode: Some("9820".to_string()),
+            latitude: Some(51.0),
+            longitude: Some(3.7),
+            ..Default::default()
+        };
+        let event = Event {
+            venue: Some(venue.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            event.location(),
+            "Zaal De Kring, Kerkstraat 1, 9820, Gent, Belgium"
+        );
+        assert_eq!(
+            venue.map_url().unwrap(),
+            "https://www.openstreetmap.org/?mlat=51&mlon=3.7#map=17/51/3.7"
+        );
+
+        let event = Event {
+            venue: Some(Venue {
+                city: Some("Merelbeke".to_string()),
+                ..venue
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            event.location(),
+            "Zaal De Kring, Kerkstraat 1, 9820, Merelbeke, Belgium"
+        );
+        assert_eq!(
+            event.venue.unwrap().address(),
+            "Zaal De Kring, Kerkstraat 1, 9820"
+        );
+    }
+}
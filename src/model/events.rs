@@ -13,68 +13,76 @@
 // limitations under the License.
 
 use super::{dancestyle::DanceStyle, event::Event, filters::Filters};
+use crate::validate::ValidationReport;
 use chrono::Utc;
-use eyre::{bail, Report, WrapErr};
+use eyre::Report;
 use log::trace;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     ffi::OsStr,
-    fs::{read_dir, read_to_string},
-    path::Path,
+    fs::read_dir,
+    path::{Path, PathBuf},
 };
 
-#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Events {
     pub events: Vec<Event>,
 }
 
+/// A list of borrowed events, which serialises in the same format as `Events` without having to
+/// clone them.
+#[derive(Clone, Debug, Serialize)]
+pub struct EventsRef<'a> {
+    pub events: Vec<&'a Event>,
+}
+
 impl Events {
-    pub fn cloned(events: Vec<&Event>) -> Self {
-        Self {
-            events: events.into_iter().cloned().collect(),
-        }
+    /// Load events from all YAML files in the given directory and its subdirectories, expanding
+    /// any recurring events into their individual occurrences. If any events are invalid then the
+    /// problems with all of them are returned together.
+    pub fn load_directory(directory: &Path) -> Result<Self, Report> {
+        trace!("Reading events from {:?}", directory);
+        let (report, events) = ValidationReport::check_path_events(directory)?;
+        report.into_events(events)
     }
 
-    /// Load events from all YAML files in the given directory and its subdirectories.
-    pub fn load_directory(directory: &Path) -> Result<Self, Report> {
-        let mut events = vec![];
+    /// Finds all YAML files in the given directory and its subdirectories, in order.
+    pub fn files_in_directory(directory: &Path) -> Result<Vec<PathBuf>, Report> {
+        let mut filenames = vec![];
         for entry in read_dir(directory)? {
             let filename = entry?.path();
-            let file_events = if filename.is_dir() {
-                Self::load_directory(&filename)?
+            if filename.is_dir() {
+                filenames.extend(Self::files_in_directory(&filename)?);
             } else if filename.extension() == Some(OsStr::new("yaml")) {
-                Self::load_file(&filename)?
+                filenames.push(filename);
             } else {
                 trace!("Not reading events from {:?}", filename);
-                continue;
-            };
-            events.extend(file_events.events);
+            }
         }
-        Ok(Self { events })
+        filenames.sort();
+        Ok(filenames)
     }
 
-    /// Load events from the given YAML file.
+    /// Load events from the given YAML file, expanding any recurring events into their individual
+    /// occurrences. If any events are invalid then the problems with all of them are returned
+    /// together.
     pub fn load_file(filename: &Path) -> Result<Self, Report> {
         trace!("Reading events from {:?}", filename);
-        let contents =
-            read_to_string(&filename).wrap_err_with(|| format!("Reading {:?}", filename))?;
-        let events = serde_yaml::from_str::<Events>(&contents)
-            .wrap_err_with(|| format!("Reading {:?}", filename))?;
-        for event in &events.events {
-            let problems = event.validate();
-            if !problems.is_empty() {
-                bail!(
-                    "Problems with event '{}' in {:?}: {:?}",
-                    event.name,
-                    filename,
-                    problems
-                );
-            }
+        let (report, events) = ValidationReport::check_path_events(filename)?;
+        report.into_events(events)
+    }
+
+    /// Builds an index from the ID of each event to its position in the list. If several events
+    /// have the same ID then the first of them is used; `validate` reports such duplicates.
+    pub fn ids(&self) -> HashMap<String, usize> {
+        let mut ids = HashMap::new();
+        for (index, event) in self.events.iter().enumerate() {
+            ids.entry(event.id()).or_insert(index);
         }
-        Ok(events)
+        ids
     }
 
     /// Get all events matching the given filters.
@@ -200,18 +208,12 @@ mod tests {
                 start_date: NaiveDate::from_ymd(2022, 1, 1),
                 end_date: NaiveDate::from_ymd(2022, 1, 1),
             },
-            details: None,
-            links: vec![],
             country: "UK".to_string(),
             city: "London".to_string(),
             styles: vec![DanceStyle::Playford],
             workshop: true,
             social: false,
-            bands: vec![],
-            callers: vec![],
-            price: None,
-            organisation: None,
-            cancelled: false,
+            ..Default::default()
         };
         let london_event_2 = Event {
             name: "Name".to_string(),
@@ -219,18 +221,12 @@ mod tests {
                 start_date: NaiveDate::from_ymd(2022, 1, 1),
                 end_date: NaiveDate::from_ymd(2022, 1, 1),
             },
-            details: None,
-            links: vec![],
             country: "UK".to_string(),
             city: "London".to_string(),
             styles: vec![DanceStyle::Playford],
             workshop: true,
             social: false,
-            bands: vec![],
-            callers: vec![],
-            price: None,
-            organisation: None,
-            cancelled: false,
+            ..Default::default()
         };
         let oxford_event = Event {
             name: "Name".to_string(),
@@ -238,18 +234,12 @@ mod tests {
                 start_date: NaiveDate::from_ymd(2022, 1, 1),
                 end_date: NaiveDate::from_ymd(2022, 1, 1),
             },
-            details: None,
-            links: vec![],
             country: "UK".to_string(),
             city: "Oxford".to_string(),
             styles: vec![DanceStyle::Playford],
             workshop: true,
             social: false,
-            bands: vec![],
-            callers: vec![],
-            price: None,
-            organisation: None,
-            cancelled: false,
+            ..Default::default()
         };
         let amsterdam_event = Event {
             name: "Name".to_string(),
@@ -257,18 +247,12 @@ mod tests {
                 start_date: NaiveDate::from_ymd(2022, 1, 1),
                 end_date: NaiveDate::from_ymd(2022, 1, 1),
             },
-            details: None,
-            links: vec![],
             country: "Netherlands".to_string(),
             city: "Amsterdam".to_string(),
             styles: vec![DanceStyle::Playford],
             workshop: true,
             social: false,
-            bands: vec![],
-            callers: vec![],
-            price: None,
-            organisation: None,
-            cancelled: false,
+            ..Default::default()
         };
         let events = Events {
             events: vec![
@@ -301,18 +285,12 @@ mod tests {
                 start_date: NaiveDate::from_ymd(1000, 1, 1),
                 end_date: NaiveDate::from_ymd(1000, 1, 1),
             },
-            details: None,
-            links: vec![],
             country: "Test".to_string(),
             city: "Test".to_string(),
             styles: vec![DanceStyle::Playford],
             workshop: true,
             social: false,
-            bands: vec![],
-            callers: vec![],
-            price: None,
-            organisation: None,
-            cancelled: false,
+            ..Default::default()
         };
         let future_event = Event {
             name: "Future".to_string(),
@@ -320,18 +298,12 @@ mod tests {
                 start_date: NaiveDate::from_ymd(3000, 1, 1),
                 end_date: NaiveDate::from_ymd(3000, 1, 1),
             },
-            details: None,
-            links: vec![],
             country: "Test".to_string(),
             city: "Test".to_string(),
             styles: vec![DanceStyle::Playford],
             workshop: true,
             social: false,
-            bands: vec![],
-            callers: vec![],
-            price: None,
-            organisation: None,
-            cancelled: false,
+            ..Default::default()
         };
         let events = Events {
             events: vec![past_event.clone(), future_event.clone()],
@@ -353,4 +325,39 @@ mod tests {
             vec![&past_event, &future_event]
         );
     }
+
+    #[test]
+    fn index_by_id() {
+        let event = Event {
+            name: "Ball d'été".to_string(),
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd(2022, 7, 2),
+                end_date: NaiveDate::from_ymd(2022, 7, 2),
+            },
+            ..Default::default()
+        };
+        let explicit_event = Event {
+            id: Some("summer-ball".to_string()),
+            ..event.clone()
+        };
+        let events = Events {
+            events: vec![event.clone(), explicit_event.clone()],
+        };
+
+        assert_eq!(event.id(), "2022-07-02-ball-d-ete-gent");
+        let ids = events.ids();
+        assert_eq!(ids.get("2022-07-02-ball-d-ete-gent"), Some(&0));
+        assert_eq!(ids.get("summer-ball"), Some(&1));
+        assert_eq!(ids.get("other"), None);
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        let event = Event::default();
+        let events = Events {
+            events: vec![event.clone(), event],
+        };
+        assert_eq!(events.ids().len(), 1);
+        assert_eq!(events.ids().get(&events.events[0].id()), Some(&0));
+    }
 }
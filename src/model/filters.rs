@@ -15,6 +15,7 @@
 use super::{
     dancestyle::DanceStyle,
     event::{Event, EventTime},
+    price::PriceKind,
 };
 use chrono::{DateTime, Utc};
 use enum_iterator::IntoEnumIterator;
@@ -36,14 +37,24 @@ pub struct Filters {
     pub caller: Option<String>,
     pub organisation: Option<String>,
     pub cancelled: Option<bool>,
+    /// Include only events which are free, or only events which aren't known to be free.
+    pub free: Option<bool>,
+    /// Include only events which can be attended for at most this amount, in the currency given by
+    /// `currency`. Free events and events asking for donations are included whatever the currency;
+    /// other events are only included if their price is in the given currency. This is ignored if no
+    /// currency is given, as amounts can't be compared without one.
+    pub max_price: Option<u32>,
+    /// The ISO 4217 code of the currency of `max_price`, e.g. "EUR".
+    pub currency: Option<String>,
 }
 
-#[derive(Copy, Clone, Debug, Deserialize, Eq, IntoEnumIterator, PartialEq, Serialize)]
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, IntoEnumIterator, PartialEq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum DateFilter {
     /// Include only events which started before the current day.
     Past,
     /// Include only events which finish on or after the current day.
+    #[default]
     Future,
     /// Include all events, past and future.
     All,
@@ -55,12 +66,6 @@ impl DateFilter {
     }
 }
 
-impl Default for DateFilter {
-    fn default() -> Self {
-        Self::Future
-    }
-}
-
 impl Display for DateFilter {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         let s = match self {
@@ -91,6 +96,9 @@ impl Filters {
             || self.caller.is_some()
             || self.organisation.is_some()
             || self.cancelled.is_some()
+            || self.free.is_some()
+            || self.max_price.is_some()
+            || self.currency.is_some()
     }
 
     pub fn to_query_string(&self) -> Result<String, Report> {
@@ -156,7 +164,7 @@ impl Filters {
             }
         }
         if let Some(organisation) = &self.organisation {
-            if &event.organisation.as_deref().unwrap_or_default() != organisation {
+            if event.organisation.as_deref().unwrap_or_default() != organisation {
                 return false;
             }
         }
@@ -165,6 +173,30 @@ impl Filters {
                 return false;
             }
         }
+        if let Some(free) = self.free {
+            let event_free = event
+                .price
+                .as_ref()
+                .is_some_and(|price| price.kind == PriceKind::Free);
+            if event_free != free {
+                return false;
+            }
+        }
+        if let (Some(max_price), Some(currency)) = (self.max_price, &self.currency) {
+            let Some(price) = &event.price else {
+                return false;
+            };
+            // Amounts in different currencies can't be compared, but free is free in any currency.
+            let same_currency = price
+                .currency
+                .as_ref()
+                .is_some_and(|price_currency| price_currency.eq_ignore_ascii_case(currency));
+            match price.min_amount() {
+                Some(0.0) => {}
+                Some(min_amount) if same_currency && min_amount <= max_price.into() => {}
+                _ => return false,
+            }
+        }
 
         true
     }
@@ -240,6 +272,14 @@ impl Filters {
         }
     }
 
+    /// Makes a new set of filters like this one but with the given free filter.
+    pub fn with_free(&self, free: Option<bool>) -> Self {
+        Self {
+            free,
+            ..self.clone()
+        }
+    }
+
     /// Makes a new set of filters like this one but with the given workshop filter.
     pub fn with_workshop(&self, workshop: Option<bool>) -> Self {
         Self {
@@ -266,3 +306,38 @@ fn owned<T: ToOwned + ?Sized>(option_ref: Option<&T>) -> Option<T::Owned> {
 fn is_default<T: Default + PartialEq>(value: &T) -> bool {
     value == &T::default()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::price::Price;
+
+    #[test]
+    fn max_price_within_currency() {
+        let now = DateTime::parse_from_rfc3339("2021-12-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let event = |price: &str| Event {
+            price: Some(Price::parse(price)),
+            ..Default::default()
+        };
+        let filters = Filters {
+            max_price: Some(10),
+            currency: Some("EUR".to_string()),
+            ..Default::default()
+        };
+        assert!(filters.matches(&event("€8"), now));
+        assert!(!filters.matches(&event("€12"), now));
+        assert!(!filters.matches(&event("£8"), now));
+        assert!(filters.matches(&event("free"), now));
+        assert!(!filters.matches(&Event::default(), now));
+
+        let filters = Filters {
+            currency: None,
+            ..filters
+        };
+        assert!(filters.matches(&event("€8"), now));
+        assert!(filters.matches(&event("£12"), now));
+        assert!(filters.matches(&Event::default(), now));
+    }
+}
@@ -16,3 +16,6 @@ pub mod dancestyle;
 pub mod event;
 pub mod events;
 pub mod filters;
+pub mod price;
+pub mod recurrence;
+pub mod source;
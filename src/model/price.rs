@@ -0,0 +1,370 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{self, Display, Formatter};
+
+/// Currency symbols which may be used in price strings, and the corresponding ISO 4217 codes.
+const CURRENCY_SYMBOLS: [(&str, &str); 3] = [("£", "GBP"), ("€", "EUR"), ("$", "USD")];
+
+/// The price of an event.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Price {
+    /// Whether the event has a fixed price, is free, or asks for donations.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub kind: PriceKind,
+    /// The ISO 4217 code of the currency of the amounts, e.g. "EUR".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// The lowest price to attend, or the suggested minimum donation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    /// The highest price to attend, if different from the lowest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// Named prices, e.g. for concessions, students or members.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiers: Vec<PriceTier>,
+    /// Any other details about the price, or a price description which couldn't be parsed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PriceKind {
+    /// The event has a fixed price or prices.
+    #[default]
+    Fixed,
+    /// The event is free to attend.
+    Free,
+    /// Attendees pay what they can or make a donation.
+    Donation,
+}
+
+/// A named price, e.g. for concessions.
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PriceTier {
+    /// The name of the tier, e.g. "student" or "concession".
+    pub name: String,
+    /// The price for this tier.
+    pub amount: f64,
+}
+
+/// The forms in which a price may be written in an events file. Older files use a free-form string.
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum PriceRepr {
+    /// A price description such as "£10", "€8-€12" or "free".
+    Text(String),
+    Structured(Price),
+}
+
+impl From<PriceRepr> for Price {
+    fn from(repr: PriceRepr) -> Self {
+        match repr {
+            PriceRepr::Text(text) => Self::parse(&text),
+            PriceRepr::Structured(price) => price,
+        }
+    }
+}
+
+/// Deserializes an optional price from either a price description string or a structured price.
+pub fn deserialize_option<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Price>, D::Error> {
+    Ok(Option::<PriceRepr>::deserialize(deserializer)?.map(Into::into))
+}
+
+/// Serializes an optional price as a price description string if it can be parsed back to the same
+/// price, or otherwise as a structured price.
+pub fn serialize_option<S: Serializer>(
+    price: &Option<Price>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match price {
+        Some(price) => {
+            let text = price.to_string();
+            if Price::parse(&text) == *price {
+                serializer.serialize_str(&text)
+            } else {
+                price.serialize(serializer)
+            }
+        }
+        None => serializer.serialize_none(),
+    }
+}
+
+impl Price {
+    /// Makes a price for a free event.
+    pub fn free() -> Self {
+        Self {
+            kind: PriceKind::Free,
+            ..Default::default()
+        }
+    }
+
+    /// Parses a price description such as "£10", "€8-€12", "free" or "donation". If the description
+    /// can't be parsed then it is kept as the description of an otherwise unknown price.
+    pub fn parse(text: &str) -> Self {
+        let trimmed = text.trim();
+        match trimmed.to_lowercase().as_str() {
+            "free" => return Self::free(),
+            "donation" | "by donation" | "pay what you can" | "pwyc" => {
+                return Self {
+                    kind: PriceKind::Donation,
+                    ..Default::default()
+                }
+            }
+            _ => {}
+        }
+
+        let mut currency = None;
+        let mut amounts = vec![];
+        for part in trimmed.split(['-', '–']) {
+            if let Some((part_currency, amount)) = parse_amount(part.trim()) {
+                if part_currency.is_some() && currency.is_some() && part_currency != currency {
+                    amounts.clear();
+                    break;
+                }
+                currency = currency.or(part_currency);
+                amounts.push(amount);
+            } else {
+                amounts.clear();
+                break;
+            }
+        }
+
+        match amounts.as_slice() {
+            [amount] => Self {
+                currency,
+                min: Some(*amount),
+                ..Default::default()
+            },
+            [min, max] if min < max => Self {
+                currency,
+                min: Some(*min),
+                max: Some(*max),
+                ..Default::default()
+            },
+            _ => Self {
+                description: Some(trimmed.to_owned()),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Gets the lowest and highest price for the event, including all tiers, if known.
+    pub fn range(&self) -> Option<(f64, f64)> {
+        if self.kind == PriceKind::Free {
+            return Some((0.0, 0.0));
+        }
+        let amounts: Vec<f64> = self
+            .min
+            .iter()
+            .chain(self.max.iter())
+            .copied()
+            .chain(self.tiers.iter().map(|tier| tier.amount))
+            .collect();
+        let min = amounts.iter().copied().reduce(f64::min)?;
+        let max = amounts.iter().copied().reduce(f64::max)?;
+        Some((min, max))
+    }
+
+    /// Gets the lowest amount which someone can pay to attend the event, if known. This is 0 for
+    /// free events and events which ask for donations.
+    pub fn min_amount(&self) -> Option<f64> {
+        match self.kind {
+            PriceKind::Free | PriceKind::Donation => Some(0.0),
+            PriceKind::Fixed => self.range().map(|(min, _)| min),
+        }
+    }
+
+    /// Formats the price including all tiers and other details.
+    pub fn long_description(&self) -> String {
+        let mut parts = vec![self.to_string()];
+        parts.extend(
+            self.tiers
+                .iter()
+                .map(|tier| format!("{} {}", tier.name, self.format_amount(tier.amount))),
+        );
+        if let Some(description) = &self.description {
+            if parts[0] != *description {
+                parts.push(description.to_owned());
+            }
+        }
+        parts.retain(|part| !part.is_empty());
+        parts.join(", ")
+    }
+
+    /// Formats the given amount in the price's currency.
+    fn format_amount(&self, amount: f64) -> String {
+        let amount = if amount.fract() == 0.0 {
+            format!("{}", amount)
+        } else {
+            format!("{:.2}", amount)
+        };
+        match self.currency.as_deref() {
+            None => amount,
+            Some(currency) => {
+                if let Some((symbol, _)) =
+                    CURRENCY_SYMBOLS.iter().find(|(_, code)| *code == currency)
+                {
+                    format!("{}{}", symbol, amount)
+                } else {
+                    format!("{} {}", amount, currency)
+                }
+            }
+        }
+    }
+}
+
+impl Display for Price {
+    /// Formats the price range compactly, e.g. "€8-€12".
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.kind {
+            PriceKind::Free => f.write_str("free"),
+            PriceKind::Donation => {
+                if let Some(min) = self.min {
+                    write!(f, "donation, {} suggested", self.format_amount(min))
+                } else {
+                    f.write_str("donation")
+                }
+            }
+            PriceKind::Fixed => match self.range() {
+                Some((min, max)) if min == max => f.write_str(&self.format_amount(min)),
+                Some((min, max)) => {
+                    write!(f, "{}-{}", self.format_amount(min), self.format_amount(max))
+                }
+                None => f.write_str(self.description.as_deref().unwrap_or_default()),
+            },
+        }
+    }
+}
+
+/// Parses an amount with an optional currency symbol, e.g. "£7.50" or "10".
+fn parse_amount(text: &str) -> Option<(Option<String>, f64)> {
+    for (symbol, code) in CURRENCY_SYMBOLS {
+        if let Some(amount) = text
+            .strip_prefix(symbol)
+            .or_else(|| text.strip_suffix(symbol))
+        {
+            return Some((Some(code.to_owned()), amount.trim().parse().ok()?));
+        }
+    }
+    Some((None, text.parse().ok()?))
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    value == &T::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_text() {
+        assert_eq!(Price::parse("Free"), Price::free());
+        assert_eq!(
+            Price::parse("£10"),
+            Price {
+                currency: Some("GBP".to_string()),
+                min: Some(10.0),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            Price::parse("€7.50-€12"),
+            Price {
+                currency: Some("EUR".to_string()),
+                min: Some(7.5),
+                max: Some(12.0),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            Price::parse("£10 (£8 concessions)"),
+            Price {
+                description: Some("£10 (£8 concessions)".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(Price::parse("€7.50-€12").to_string(), "€7.50-€12");
+        assert_eq!(Price::parse("£10 or so").to_string(), "£10 or so");
+        assert_eq!(Price::free().to_string(), "free");
+        let price = Price {
+            currency: Some("GBP".to_string()),
+            min: Some(10.0),
+            tiers: vec![PriceTier {
+                name: "student".to_string(),
+                amount: 6.0,
+            }],
+            ..Default::default()
+        };
+        assert_eq!(price.to_string(), "£6-£10");
+        assert_eq!(price.long_description(), "£6-£10, student £6");
+        assert_eq!(price.min_amount(), Some(6.0));
+    }
+
+    #[test]
+    fn deserialize_string_or_structured() {
+        assert_eq!(
+            Price::from(serde_yaml::from_str::<PriceRepr>("\"£10\"").unwrap()),
+            Price::parse("£10")
+        );
+        assert_eq!(
+            Price::from(
+                serde_yaml::from_str::<PriceRepr>("kind: donation\ncurrency: EUR\nmin: 5").unwrap()
+            ),
+            Price {
+                kind: PriceKind::Donation,
+                currency: Some("EUR".to_string()),
+                min: Some(5.0),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn serialize_string_if_possible() {
+        #[derive(Serialize)]
+        struct Wrapper {
+            #[serde(serialize_with = "serialize_option")]
+            price: Option<Price>,
+        }
+        let yaml = |price: Price| serde_yaml::to_string(&Wrapper { price: Some(price) }).unwrap();
+        assert_eq!(yaml(Price::parse("€8-€12")), "---\nprice: €8-€12\n");
+        assert_eq!(yaml(Price::free()), "---\nprice: free\n");
+        assert_eq!(
+            yaml(Price {
+                currency: Some("GBP".to_string()),
+                min: Some(10.0),
+                tiers: vec![PriceTier {
+                    name: "student".to_string(),
+                    amount: 6.0,
+                }],
+                ..Default::default()
+            }),
+            "---\nprice:\n  currency: GBP\n  min: 10.0\n  tiers:\n    - name: student\n      amount: 6.0\n"
+        );
+    }
+}
@@ -0,0 +1,419 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{
+    event::Event,
+    price::{self, Price, PriceRepr},
+};
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// The maximum number of years after the first occurrence for which a recurring event may be
+/// expanded, to avoid a mistyped `until` date producing an unbounded number of occurrences.
+const MAX_YEARS: i32 = 5;
+
+/// A rule describing how an event repeats.
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Recurrence {
+    /// How often the event repeats.
+    pub frequency: Frequency,
+    /// The number of weeks or months between occurrences.
+    #[serde(
+        default = "default_interval",
+        skip_serializing_if = "is_default_interval"
+    )]
+    pub interval: u32,
+    /// The last date on which the event may start. This must be within five years of the first
+    /// occurrence.
+    pub until: NaiveDate,
+    /// Dates on which the event would otherwise happen but doesn't.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub except: Vec<NaiveDate>,
+    /// Changes to individual occurrences of the event.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<Override>,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+pub enum Frequency {
+    /// The event repeats on the same day of the week.
+    #[serde(rename = "weekly")]
+    Weekly,
+    /// The event repeats on the same day of the week in the same week of the month as the first
+    /// occurrence, e.g. the second Saturday of every month.
+    #[serde(rename = "monthly")]
+    Monthly,
+    /// The event repeats on the last occurrence of the same day of the week in every month, e.g.
+    /// the last Friday of every month.
+    #[serde(rename = "monthly-last")]
+    MonthlyLast,
+}
+
+/// Changes to a single occurrence of a recurring event.
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Override {
+    /// The date of the occurrence to change.
+    pub date: NaiveDate,
+    /// A different name for this occurrence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Different details for this occurrence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+    /// Different bands for this occurrence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bands: Option<Vec<String>>,
+    /// Different callers for this occurrence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callers: Option<Vec<String>>,
+    /// Whether this occurrence has been cancelled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancelled: Option<bool>,
+    /// A different price for this occurrence.
+    #[serde(
+        default,
+        deserialize_with = "price::deserialize_option",
+        serialize_with = "price::serialize_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<PriceRepr>")]
+    pub price: Option<Price>,
+}
+
+impl Recurrence {
+    /// Check that the recurrence rule is valid for an event starting on the given date. Returns an
+    /// empty list if it is, or a list of problems if not.
+    pub fn validate(&self, first: NaiveDate) -> Vec<&'static str> {
+        let mut problems = vec![];
+
+        if self.interval == 0 {
+            problems.push("Recurrence interval must be at least 1.");
+        }
+        if self.until < first {
+            problems.push("Recurrence must not end before the first occurrence.");
+        }
+        if self.until > latest_until(first) {
+            problems.push("Recurrence must end within 5 years of the first occurrence.");
+        }
+        match self.frequency {
+            Frequency::Weekly => {}
+            Frequency::Monthly => {
+                if first.day() > 28 {
+                    problems.push(
+                        "Monthly recurrence can't start after the 28th of the month, as not every \
+                        month has a fifth such weekday. Use monthly-last for the last weekday of \
+                        the month.",
+                    );
+                }
+            }
+            Frequency::MonthlyLast => {
+                if last_weekday(first.year(), first.month(), first.weekday()) != first {
+                    problems.push("Monthly-last recurrence must start on the last such weekday.");
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            let dates = self.all_dates(first);
+            if !self.except.iter().all(|date| dates.contains(date)) {
+                problems.push("Recurrence exceptions must be dates on which the event recurs.");
+            }
+            if !self
+                .overrides
+                .iter()
+                .all(|date_override| dates.contains(&date_override.date))
+            {
+                problems.push("Recurrence overrides must be for dates on which the event recurs.");
+            }
+        }
+
+        problems
+    }
+
+    /// Gets the dates on which the event happens, given the date of the first occurrence.
+    pub fn dates(&self, first: NaiveDate) -> Vec<NaiveDate> {
+        self.all_dates(first)
+            .into_iter()
+            .filter(|date| !self.except.contains(date))
+            .collect()
+    }
+
+    /// Gets all the dates on which the event recurs, including exceptions. Dates more than
+    /// `MAX_YEARS` after the first are never included, and months without the given weekday are
+    /// skipped, even if the rule is invalid.
+    fn all_dates(&self, first: NaiveDate) -> Vec<NaiveDate> {
+        let mut dates = vec![];
+        if self.interval == 0 {
+            return dates;
+        }
+        let until = self.until.min(latest_until(first));
+        match self.frequency {
+            Frequency::Weekly => {
+                let mut date = Some(first);
+                while let Some(current) = date.filter(|&date| date <= until) {
+                    dates.push(current);
+                    date = current.checked_add_signed(Duration::weeks(self.interval.into()));
+                }
+            }
+            Frequency::Monthly | Frequency::MonthlyLast => {
+                let week = (first.day() - 1) / 7;
+                let (mut year, mut month) = (first.year(), first.month());
+                loop {
+                    match NaiveDate::from_ymd_opt(year, month, 1) {
+                        Some(start) if start <= until => {}
+                        _ => break,
+                    }
+                    let date = if self.frequency == Frequency::Monthly {
+                        nth_weekday(year, month, first.weekday(), week)
+                    } else {
+                        Some(last_weekday(year, month, first.weekday()))
+                    };
+                    if let Some(date) = date.filter(|&date| date >= first && date <= until) {
+                        dates.push(date);
+                    }
+                    let months = u64::from(month - 1) + u64::from(self.interval);
+                    year += (months / 12) as i32;
+                    month = (months % 12) as u32 + 1;
+                }
+            }
+        }
+        dates
+    }
+}
+
+impl Override {
+    /// Applies the changes in this override to the given occurrence.
+    pub fn apply(&self, event: &mut Event) {
+        if let Some(name) = &self.name {
+            event.name = name.to_owned();
+        }
+        if let Some(details) = &self.details {
+            event.details = Some(details.to_owned());
+        }
+        if let Some(bands) = &self.bands {
+            event.bands = bands.to_owned();
+        }
+        if let Some(callers) = &self.callers {
+            event.callers = callers.to_owned();
+        }
+        if let Some(price) = &self.price {
+            event.price = Some(price.to_owned());
+        }
+        if let Some(cancelled) = self.cancelled {
+            event.cancelled = cancelled;
+        }
+    }
+}
+
+fn default_interval() -> u32 {
+    1
+}
+
+fn is_default_interval(interval: &u32) -> bool {
+    *interval == default_interval()
+}
+
+/// Gets the latest `until` date allowed for a recurrence starting on the given date.
+fn latest_until(first: NaiveDate) -> NaiveDate {
+    let year = first.year() + MAX_YEARS;
+    // The 29th of February may not exist in the later year.
+    first
+        .with_year(year)
+        .unwrap_or_else(|| NaiveDate::from_ymd(year, 2, 28))
+}
+
+/// Gets the date of the given weekday in the given week (counting from 0) of the given month, or
+/// `None` if the month doesn't have that many of the weekday.
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, week: u32) -> Option<NaiveDate> {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, week as u8 + 1)
+}
+
+/// Gets the date of the last occurrence of the given weekday in the given month.
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    // Every month has at least four of each weekday.
+    let mut date = NaiveDate::from_weekday_of_month(year, month, weekday, 4);
+    while (date + Duration::weeks(1)).month() == month {
+        date += Duration::weeks(1);
+    }
+    date
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::event::EventTime;
+    use chrono::DateTime;
+    use chrono_tz::Europe::London;
+
+    fn recurrence(frequency: Frequency, until: NaiveDate) -> Recurrence {
+        Recurrence {
+            frequency,
+            interval: 1,
+            until,
+            except: vec![],
+            overrides: vec![],
+        }
+    }
+
+    #[test]
+    fn weekly_with_exceptions() {
+        let recurrence = Recurrence {
+            interval: 2,
+            except: vec![NaiveDate::from_ymd(2022, 3, 15)],
+            ..recurrence(Frequency::Weekly, NaiveDate::from_ymd(2022, 4, 1))
+        };
+        let first = NaiveDate::from_ymd(2022, 3, 1);
+        assert_eq!(recurrence.validate(first), Vec::<&str>::new());
+        assert_eq!(
+            recurrence.dates(first),
+            vec![
+                NaiveDate::from_ymd(2022, 3, 1),
+                NaiveDate::from_ymd(2022, 3, 29),
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_nth_weekday() {
+        // The second Saturday of the month.
+        let recurrence = recurrence(Frequency::Monthly, NaiveDate::from_ymd(2022, 5, 31));
+        let first = NaiveDate::from_ymd(2022, 1, 8);
+        assert_eq!(recurrence.validate(first), Vec::<&str>::new());
+        assert_eq!(
+            recurrence.dates(first),
+            vec![
+                NaiveDate::from_ymd(2022, 1, 8),
+                NaiveDate::from_ymd(2022, 2, 12),
+                NaiveDate::from_ymd(2022, 3, 12),
+                NaiveDate::from_ymd(2022, 4, 9),
+                NaiveDate::from_ymd(2022, 5, 14),
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_last_weekday() {
+        let recurrence = Recurrence {
+            interval: 5,
+            ..recurrence(Frequency::MonthlyLast, NaiveDate::from_ymd(2023, 1, 1))
+        };
+        let first = NaiveDate::from_ymd(2022, 1, 28);
+        assert_eq!(
+            recurrence.dates(first),
+            vec![
+                NaiveDate::from_ymd(2022, 1, 28),
+                NaiveDate::from_ymd(2022, 6, 24),
+                NaiveDate::from_ymd(2022, 11, 25),
+            ]
+        );
+    }
+
+    #[test]
+    fn limited_duration() {
+        let recurrence = recurrence(Frequency::Weekly, NaiveDate::from_ymd(3000, 1, 1));
+        let first = NaiveDate::from_ymd(2022, 3, 1);
+        assert_eq!(
+            recurrence.validate(first),
+            vec!["Recurrence must end within 5 years of the first occurrence."]
+        );
+        let dates = recurrence.dates(first);
+        assert_eq!(dates.len(), 261);
+        assert_eq!(dates.last(), Some(&NaiveDate::from_ymd(2027, 2, 23)));
+    }
+
+    #[test]
+    fn invalid_rules_skip_missing_dates() {
+        // The fifth Saturday of the month, which most months don't have.
+        let recurrence = recurrence(Frequency::Monthly, NaiveDate::from_ymd(2022, 5, 31));
+        let first = NaiveDate::from_ymd(2022, 1, 29);
+        assert_eq!(recurrence.validate(first).len(), 1);
+        assert_eq!(
+            recurrence.dates(first),
+            vec![
+                NaiveDate::from_ymd(2022, 1, 29),
+                NaiveDate::from_ymd(2022, 4, 30),
+            ]
+        );
+
+        let recurrence = Recurrence {
+            interval: u32::MAX,
+            ..recurrence
+        };
+        assert_eq!(recurrence.dates(first), vec![first]);
+        let recurrence = Recurrence {
+            frequency: Frequency::Weekly,
+            ..recurrence
+        };
+        assert_eq!(recurrence.dates(first), vec![first]);
+    }
+
+    #[test]
+    fn invalid_exceptions() {
+        let recurrence = Recurrence {
+            except: vec![NaiveDate::from_ymd(2022, 3, 2)],
+            ..recurrence(Frequency::Weekly, NaiveDate::from_ymd(2022, 4, 1))
+        };
+        assert_eq!(
+            recurrence.validate(NaiveDate::from_ymd(2022, 3, 1)),
+            vec!["Recurrence exceptions must be dates on which the event recurs."]
+        );
+    }
+
+    #[test]
+    fn occurrences_keep_local_time() {
+        let event = Event {
+            name: "Weekly class".to_string(),
+            time: EventTime::DateTime {
+                start: DateTime::parse_from_rfc3339("2022-03-22T19:30:00+00:00").unwrap(),
+                end: DateTime::parse_from_rfc3339("2022-03-22T21:30:00+00:00").unwrap(),
+            },
+            timezone: Some(London),
+            country: "UK".to_string(),
+            city: "London".to_string(),
+            workshop: true,
+            social: false,
+            recurrence: Some(Recurrence {
+                overrides: vec![Override {
+                    date: NaiveDate::from_ymd(2022, 3, 29),
+                    name: None,
+                    details: None,
+                    bands: Some(vec!["Band".to_string()]),
+                    callers: None,
+                    cancelled: Some(true),
+                    price: None,
+                }],
+                ..recurrence(Frequency::Weekly, NaiveDate::from_ymd(2022, 3, 29))
+            }),
+            ..Default::default()
+        };
+        assert_eq!(event.validate(), Vec::<&str>::new());
+
+        let occurrences = event.occurrences();
+        assert_eq!(occurrences.len(), 2);
+        assert_eq!(occurrences[0].recurrence, None);
+        assert!(!occurrences[0].cancelled);
+        assert_eq!(
+            occurrences[1].time,
+            EventTime::DateTime {
+                start: DateTime::parse_from_rfc3339("2022-03-29T19:30:00+01:00").unwrap(),
+                end: DateTime::parse_from_rfc3339("2022-03-29T21:30:00+01:00").unwrap(),
+            }
+        );
+        assert!(occurrences[1].cancelled);
+        assert_eq!(occurrences[1].bands, vec!["Band".to_string()]);
+    }
+}
@@ -0,0 +1,56 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Where an imported event came from.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Source {
+    /// The name of the importer which imported the event, e.g. "balbende".
+    pub importer: String,
+    /// The ID of the event in the upstream source, used to match it when importing again.
+    pub id: String,
+    /// When the event was fetched from the upstream source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetched: Option<DateTime<Utc>>,
+    /// The licence under which the upstream data may be used, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub licence: Option<String>,
+    /// Credit to show for the upstream source, e.g. "Event data from folkbalbende.be".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<String>,
+}
+
+impl Source {
+    /// Returns whether the two sources describe the same upstream event, ignoring when it was
+    /// fetched and how it is licensed.
+    pub fn same_upstream(&self, other: &Source) -> bool {
+        self.importer == other.importer && self.id == other.id
+    }
+
+    /// Describes the source for display, crediting it and giving the licence if known.
+    pub fn credit(&self) -> String {
+        let mut credit = self
+            .attribution
+            .clone()
+            .unwrap_or_else(|| format!("Imported from {}", self.importer));
+        if let Some(licence) = &self.licence {
+            credit += &format!(" ({})", licence);
+        }
+        credit
+    }
+}
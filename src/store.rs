@@ -0,0 +1,239 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::{event::Event, events::Events};
+use chrono::{DateTime, Utc};
+use eyre::{bail, Report, WrapErr};
+use log::{error, info};
+use std::{
+    collections::HashMap,
+    fs::metadata,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    time::interval,
+};
+
+/// The events currently being served, which may be reloaded from disk while the server is running.
+#[derive(Clone, Debug)]
+pub struct EventStore {
+    directory: PathBuf,
+    served: Arc<RwLock<Served>>,
+    status: Arc<RwLock<ReloadStatus>>,
+    files: Arc<Mutex<HashMap<PathBuf, LoadedFile>>>,
+    /// The modification times of all files when the last reload failed, so that the same files
+    /// aren't loaded again until one of them changes.
+    failed: Arc<Mutex<Option<HashMap<PathBuf, SystemTime>>>>,
+}
+
+/// The result of the most recent attempts to load events.
+#[derive(Clone, Debug)]
+pub struct ReloadStatus {
+    /// When the events currently being served were loaded.
+    pub loaded_at: DateTime<Utc>,
+    /// The number of events currently being served.
+    pub event_count: usize,
+    /// The number of files from which the events currently being served were loaded.
+    pub file_count: usize,
+    /// The time and error message of the last failed reload, if it failed more recently than the
+    /// last successful load.
+    pub last_error: Option<(DateTime<Utc>, String)>,
+}
+
+/// The events currently being served, along with an index from their IDs to their positions.
+#[derive(Debug)]
+struct Served {
+    events: Arc<Events>,
+    ids: HashMap<String, usize>,
+}
+
+impl Served {
+    fn new(events: Events) -> Self {
+        Self {
+            ids: events.ids(),
+            events: Arc::new(events),
+        }
+    }
+}
+
+/// Events loaded from a single file, along with the file's modification time when they were loaded.
+#[derive(Clone, Debug)]
+struct LoadedFile {
+    modified: SystemTime,
+    events: Vec<Event>,
+}
+
+impl EventStore {
+    /// Loads events from all YAML files in the given directory and its subdirectories.
+    pub fn load(directory: &Path) -> Result<Self, Report> {
+        let files = load_changed_files(&modification_times(directory)?, &HashMap::new())?;
+        let events = combine(&files);
+        let status = ReloadStatus {
+            loaded_at: Utc::now(),
+            event_count: events.events.len(),
+            file_count: files.len(),
+            last_error: None,
+        };
+        Ok(Self {
+            directory: directory.to_owned(),
+            served: Arc::new(RwLock::new(Served::new(events))),
+            status: Arc::new(RwLock::new(status)),
+            files: Arc::new(Mutex::new(files)),
+            failed: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Gets the events currently being served.
+    pub fn events(&self) -> Arc<Events> {
+        self.served.read().unwrap().events.clone()
+    }
+
+    /// Finds the event currently being served with the given ID, if any.
+    pub fn find(&self, id: &str) -> Option<Event> {
+        let served = self.served.read().unwrap();
+        let index = *served.ids.get(id)?;
+        Some(served.events.events[index].clone())
+    }
+
+    /// Gets the status of the most recent reload.
+    pub fn status(&self) -> ReloadStatus {
+        self.status.read().unwrap().clone()
+    }
+
+    /// Reloads any files which have been added, changed or removed since they were last loaded. If
+    /// any of them fail to load then the events currently being served are kept, and the error is
+    /// recorded in the status. Nothing is reloaded if the files are unchanged since the last
+    /// successful or failed reload.
+    pub fn reload(&self) {
+        let mut files = self.files.lock().unwrap();
+        let mut failed = self.failed.lock().unwrap();
+        let result = modification_times(&self.directory).and_then(|modified| {
+            let changed = modified.len() != files.len()
+                || modified.iter().any(|(filename, modified)| {
+                    files.get(filename).map(|file| file.modified) != Some(*modified)
+                });
+            if !changed || failed.as_ref() == Some(&modified) {
+                return Ok(None);
+            }
+            let loaded = load_changed_files(&modified, &files).map(|new_files| {
+                let events = combine(&new_files);
+                (new_files, events)
+            });
+            match loaded {
+                Ok(loaded) => Ok(Some(loaded)),
+                Err(e) => {
+                    *failed = Some(modified);
+                    Err(e)
+                }
+            }
+        });
+        match result {
+            Ok(Some((new_files, events))) => {
+                info!(
+                    "Reloaded {} events from {} files",
+                    events.events.len(),
+                    new_files.len()
+                );
+                *self.status.write().unwrap() = ReloadStatus {
+                    loaded_at: Utc::now(),
+                    event_count: events.events.len(),
+                    file_count: new_files.len(),
+                    last_error: None,
+                };
+                *self.served.write().unwrap() = Served::new(events);
+                *files = new_files;
+                *failed = None;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!("Failed to reload events, keeping old events: {:#}", e);
+                self.status.write().unwrap().last_error = Some((Utc::now(), format!("{:#}", e)));
+            }
+        }
+    }
+
+    /// Reloads events whenever the process receives SIGHUP, and otherwise polls for changes at the
+    /// given interval. Never returns.
+    pub async fn watch(self, poll_interval: Duration) -> Result<(), Report> {
+        let mut hangup = signal(SignalKind::hangup())?;
+        let mut interval = interval(poll_interval);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = hangup.recv() => info!("Received SIGHUP, reloading events"),
+            }
+            let store = self.clone();
+            tokio::task::spawn_blocking(move || store.reload()).await?;
+        }
+    }
+}
+
+/// Gets the modification times of all event files in the given directory.
+fn modification_times(directory: &Path) -> Result<HashMap<PathBuf, SystemTime>, Report> {
+    Events::files_in_directory(directory)?
+        .into_iter()
+        .map(|filename| {
+            let modified = metadata(&filename)
+                .and_then(|metadata| metadata.modified())
+                .wrap_err_with(|| format!("Reading {:?}", filename))?;
+            Ok((filename, modified))
+        })
+        .collect()
+}
+
+/// Loads the given event files, reusing the already-loaded events for any which haven't been
+/// modified since they were loaded. If any files have problems then they are all reported together.
+fn load_changed_files(
+    modified: &HashMap<PathBuf, SystemTime>,
+    previous: &HashMap<PathBuf, LoadedFile>,
+) -> Result<HashMap<PathBuf, LoadedFile>, Report> {
+    let mut files = HashMap::new();
+    let mut errors = vec![];
+    for (filename, &modified) in modified {
+        let file = match previous.get(filename) {
+            Some(file) if file.modified == modified => file.clone(),
+            _ => match Events::load_file(filename) {
+                Ok(events) => LoadedFile {
+                    modified,
+                    events: events.events,
+                },
+                Err(e) => {
+                    errors.push(e.to_string());
+                    continue;
+                }
+            },
+        };
+        files.insert(filename.to_owned(), file);
+    }
+    if !errors.is_empty() {
+        errors.sort();
+        bail!("{}", errors.join("\n"));
+    }
+    Ok(files)
+}
+
+/// Combines the events from all the given files, in order of filename.
+fn combine(files: &HashMap<PathBuf, LoadedFile>) -> Events {
+    let mut filenames: Vec<_> = files.keys().collect();
+    filenames.sort();
+    Events {
+        events: filenames
+            .into_iter()
+            .flat_map(|filename| files[filename].events.iter().cloned())
+            .collect(),
+    }
+}
@@ -0,0 +1,496 @@
+// Copyright 2022 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::{event::Event, events::Events};
+use eyre::{bail, Report};
+use log::warn;
+use serde::Serialize;
+use serde_yaml::Value;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+use yaml_rust::{
+    parser::{Event as YamlEvent, MarkedEventReceiver, Parser},
+    scanner::Marker,
+};
+
+/// All the problems found with a set of event files.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ValidationReport {
+    /// The number of files checked.
+    pub files: usize,
+    /// The number of events checked, after expanding recurring events.
+    pub events: usize,
+    pub problems: Vec<Problem>,
+}
+
+/// A single problem with an event file.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Problem {
+    pub severity: Severity,
+    pub file: PathBuf,
+    /// The line number of the problem, or of the event to which it applies, counting from 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// The column number of the problem, or of the event to which it applies, counting from 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    /// The name of the event to which the problem applies, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>,
+    pub message: String,
+}
+
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The file can't be loaded until this is fixed.
+    Error,
+    /// The file can be loaded, but probably isn't what was intended.
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        })
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        write!(f, ": {}: ", self.severity)?;
+        if let Some(event) = &self.event {
+            write!(f, "event '{}': ", event)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+/// A valid event, along with where it was defined.
+#[derive(Clone, Debug)]
+pub struct LocatedEvent {
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// The event as defined in the file, before expanding any recurrence.
+    pub event: Event,
+}
+
+impl Problem {
+    /// Makes a problem applying to the given event.
+    pub fn for_event(severity: Severity, event: &LocatedEvent, message: String) -> Self {
+        Self {
+            severity,
+            file: event.file.clone(),
+            line: event.line,
+            column: event.column,
+            event: Some(event.event.name.clone()),
+            message,
+        }
+    }
+}
+
+impl ValidationReport {
+    /// Checks all event files in the given file or directory, collecting every problem rather than
+    /// stopping at the first.
+    pub fn check_path(path: &Path) -> Result<Self, Report> {
+        Ok(Self::check_path_events(path)?.0)
+    }
+
+    /// Checks all event files in the given file or directory like `check_path`, and also returns
+    /// all the valid events found along with their positions.
+    pub fn check_path_events(path: &Path) -> Result<(Self, Vec<LocatedEvent>), Report> {
+        let filenames = if path.is_dir() {
+            Events::files_in_directory(path)?
+        } else {
+            vec![path.to_owned()]
+        };
+        let mut report = Self::default();
+        let mut events = vec![];
+        let mut ids = HashMap::new();
+        for filename in &filenames {
+            match read_to_string(filename) {
+                Ok(contents) => events.extend(report.check_file(filename, &contents, &mut ids)),
+                Err(e) => report.problems.push(Problem {
+                    severity: Severity::Error,
+                    file: filename.to_owned(),
+                    line: None,
+                    column: None,
+                    event: None,
+                    message: format!("Can't read file: {}", e),
+                }),
+            }
+        }
+        Ok((report, events))
+    }
+
+    /// Returns the given valid events, with any recurring events expanded into their individual
+    /// occurrences, if the report has no errors. Otherwise returns all the errors together. Any
+    /// warnings are logged.
+    pub fn into_events(self, events: Vec<LocatedEvent>) -> Result<Events, Report> {
+        let mut errors = vec![];
+        for problem in &self.problems {
+            match problem.severity {
+                Severity::Error => errors.push(problem.to_string()),
+                Severity::Warning => warn!("{}", problem),
+            }
+        }
+        if !errors.is_empty() {
+            bail!(
+                "{} errors loading events:\n{}",
+                errors.len(),
+                errors.join("\n")
+            );
+        }
+        Ok(Events {
+            events: events
+                .into_iter()
+                .flat_map(|located| located.event.occurrences())
+                .collect(),
+        })
+    }
+
+    /// Returns whether any of the problems are errors rather than warnings.
+    pub fn has_errors(&self) -> bool {
+        self.problems
+            .iter()
+            .any(|problem| problem.severity == Severity::Error)
+    }
+
+    /// Counts the problems with the given severity.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.problems
+            .iter()
+            .filter(|problem| problem.severity == severity)
+            .count()
+    }
+
+    /// Checks the given contents of an events file, adding any problems to the report and returning
+    /// the valid events. `ids` keeps track of where each event ID has been seen so far, to detect
+    /// duplicates across files.
+    fn check_file(
+        &mut self,
+        filename: &Path,
+        contents: &str,
+        ids: &mut HashMap<String, (PathBuf, Option<Marker>)>,
+    ) -> Vec<LocatedEvent> {
+        self.files += 1;
+        let problem = |severity, position: Option<Marker>, event: Option<&str>, message| Problem {
+            severity,
+            file: filename.to_owned(),
+            line: position.map(|marker| marker.line()),
+            column: position.map(|marker| marker.col() + 1),
+            event: event.map(ToOwned::to_owned),
+            message,
+        };
+
+        let items = match parse_events_file(contents) {
+            Ok(items) => items,
+            Err(e) => {
+                self.problems.push(Problem {
+                    line: e.location().map(|location| location.line()),
+                    column: e.location().map(|location| location.column()),
+                    ..problem(Severity::Error, None, None, e.to_string())
+                });
+                return vec![];
+            }
+        };
+        if items.is_empty() {
+            self.problems.push(problem(
+                Severity::Warning,
+                None,
+                None,
+                "File contains no events.".to_string(),
+            ));
+        }
+
+        let positions = event_positions(contents);
+        let mut events = vec![];
+        for (i, item) in items.into_iter().enumerate() {
+            let position = positions.get(i).copied();
+            let name = item
+                .get("name")
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned);
+            let event = match serde_yaml::from_value::<Event>(item) {
+                Ok(event) => event,
+                Err(e) => {
+                    self.problems.push(problem(
+                        Severity::Error,
+                        position,
+                        name.as_deref(),
+                        e.to_string(),
+                    ));
+                    continue;
+                }
+            };
+            let problems = event.validate();
+            if !problems.is_empty() {
+                self.problems.extend(problems.into_iter().map(|message| {
+                    problem(Severity::Error, position, name.as_deref(), message.into())
+                }));
+                continue;
+            }
+            events.push(LocatedEvent {
+                file: filename.to_owned(),
+                line: position.map(|marker| marker.line()),
+                column: position.map(|marker| marker.col() + 1),
+                event: event.clone(),
+            });
+            for occurrence in event.occurrences() {
+                self.events += 1;
+                let id = occurrence.id();
+                if let Some((other_file, other_position)) =
+                    ids.insert(id.clone(), (filename.to_owned(), position))
+                {
+                    let other_line = other_position
+                        .map(|marker| format!(":{}", marker.line()))
+                        .unwrap_or_default();
+                    // Derived IDs may legitimately collide, e.g. for afternoon and evening
+                    // sessions, but only the first can then be linked to by its ID.
+                    let (severity, hint) = if occurrence.id.is_some() {
+                        (Severity::Error, "")
+                    } else {
+                        (
+                            Severity::Warning,
+                            "; give one of them an explicit ID so it can be linked to",
+                        )
+                    };
+                    self.problems.push(problem(
+                        severity,
+                        position,
+                        Some(&occurrence.name),
+                        format!(
+                            "Event ID '{}' is also used at {}{}{}.",
+                            id,
+                            other_file.display(),
+                            other_line,
+                            hint
+                        ),
+                    ));
+                }
+            }
+        }
+        events
+    }
+}
+
+/// Parses the top level of an events file, returning the unparsed events.
+fn parse_events_file(contents: &str) -> Result<Vec<Value>, serde_yaml::Error> {
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct RawEvents {
+        events: Vec<Value>,
+    }
+
+    Ok(serde_yaml::from_str::<RawEvents>(contents)?.events)
+}
+
+/// Finds the positions of the items in the `events` list of an events file. Returns an empty list
+/// if the file can't be parsed.
+pub fn event_positions(contents: &str) -> Vec<Marker> {
+    let mut receiver = EventPositions::default();
+    match Parser::new(contents.chars()).load(&mut receiver, false) {
+        Ok(()) => receiver.positions,
+        Err(_) => vec![],
+    }
+}
+
+/// Receives YAML parser events, and keeps track of where the items of the top-level `events`
+/// sequence start.
+#[derive(Default)]
+struct EventPositions {
+    /// The number of collections which the parser is currently inside.
+    depth: usize,
+    /// Whether the parser is currently inside the value for the top-level `events` key.
+    in_events: bool,
+    /// Whether the last top-level node was the key `events`.
+    after_events_key: bool,
+    /// Whether an item of the `events` sequence has started, but its position hasn't been recorded
+    /// yet.
+    item_started: bool,
+    /// Whether the next top-level node will be a key rather than a value.
+    expecting_key: bool,
+    positions: Vec<Marker>,
+}
+
+impl MarkedEventReceiver for EventPositions {
+    fn on_event(&mut self, event: YamlEvent, marker: Marker) {
+        match event {
+            YamlEvent::Scalar(..) | YamlEvent::Alias(_) => {
+                if self.depth == 1 {
+                    self.after_events_key = self.expecting_key
+                        && matches!(&event, YamlEvent::Scalar(value, ..) if value == "events");
+                    self.expecting_key = !self.expecting_key;
+                } else if self.depth == 2 && self.in_events {
+                    self.positions.push(marker);
+                } else if self.depth == 3 && self.item_started {
+                    // The start of a block mapping is reported at its first value, so use the
+                    // position of its first key instead.
+                    self.positions.push(marker);
+                    self.item_started = false;
+                }
+            }
+            YamlEvent::SequenceStart(_) | YamlEvent::MappingStart(_) => {
+                if self.depth == 0 {
+                    self.expecting_key = true;
+                } else if self.depth == 1 && self.after_events_key {
+                    self.in_events = true;
+                } else if self.depth == 2 && self.in_events {
+                    self.item_started = true;
+                } else if self.depth == 3 && self.item_started {
+                    self.positions.push(marker);
+                    self.item_started = false;
+                }
+                self.depth += 1;
+            }
+            YamlEvent::SequenceEnd | YamlEvent::MappingEnd => {
+                self.depth -= 1;
+                if self.depth == 1 {
+                    self.in_events = false;
+                    self.after_events_key = false;
+                    self.expecting_key = !self.expecting_key;
+                } else if self.depth == 2 && self.item_started {
+                    // An empty collection.
+                    self.positions.push(marker);
+                    self.item_started = false;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(contents: &str) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        report.check_file(Path::new("test.yaml"), contents, &mut HashMap::new());
+        report
+    }
+
+    #[test]
+    fn reports_all_problems_with_positions() {
+        let report = check(
+            r#"
+events:
+  - name: Good
+    start_date: 2022-01-01
+    end_date: 2022-01-01
+    country: UK
+    city: London
+    styles: [balfolk]
+    workshop: true
+  - name: No styles
+    start_date: 2022-01-02
+    end_date: 2022-01-02
+    country: UK
+    city: London
+    styles: []
+    workshop: true
+  - name: Bad field
+    colour: blue
+"#,
+        );
+        assert_eq!(report.events, 1);
+        assert_eq!(report.problems.len(), 2);
+        assert!(report.has_errors());
+        assert_eq!(report.problems[0].line, Some(10));
+        assert_eq!(report.problems[0].column, Some(5));
+        assert_eq!(report.problems[0].event.as_deref(), Some("No styles"));
+        assert_eq!(report.problems[1].line, Some(17));
+        assert_eq!(report.problems[1].event.as_deref(), Some("Bad field"));
+    }
+
+    #[test]
+    fn loading_reports_all_errors() {
+        let contents = r#"
+events:
+  - name: No styles
+    start_date: 2022-01-02
+    end_date: 2022-01-02
+    country: UK
+    city: London
+    styles: []
+    workshop: true
+  - name: Backwards
+    start_date: 2022-01-02
+    end_date: 2022-01-01
+    country: UK
+    city: London
+    styles: [balfolk]
+    workshop: true
+"#;
+        let mut report = ValidationReport::default();
+        let events = report.check_file(Path::new("test.yaml"), contents, &mut HashMap::new());
+        let error = report.into_events(events).unwrap_err().to_string();
+        assert!(error.starts_with("2 errors loading events:\n"));
+        assert!(error.contains("test.yaml:3:5: error: event 'No styles': "));
+        assert!(error.contains("test.yaml:10:5: error: event 'Backwards': "));
+
+        let mut report = ValidationReport::default();
+        let events = report.check_file(Path::new("test.yaml"), "events: []\n", &mut HashMap::new());
+        assert_eq!(report.into_events(events).unwrap().events, vec![]);
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let report = check("events:\n  - name: [\n");
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.problems[0].severity, Severity::Error);
+        assert!(report.problems[0].line.is_some());
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        let event = |id: &str| {
+            format!(
+                "  - name: Bal\n{}    start_date: 2022-01-01\n    end_date: 2022-01-01\n    \
+                 country: UK\n    city: London\n    styles: [balfolk]\n    social: true\n",
+                id
+            )
+        };
+        let report = check(&format!("events:\n{}{}", event(""), event("")));
+        assert_eq!(report.events, 2);
+        assert!(!report.has_errors());
+        assert_eq!(report.count(Severity::Warning), 1);
+        assert_eq!(report.problems[0].line, Some(9));
+
+        let explicit = "    id: bal\n";
+        let report = check(&format!("events:\n{}{}", event(explicit), event(explicit)));
+        assert!(report.has_errors());
+    }
+
+    #[test]
+    fn empty_file_is_warning() {
+        let report = check("events: []\n");
+        assert!(!report.has_errors());
+        assert_eq!(report.count(Severity::Warning), 1);
+    }
+}